@@ -1,6 +1,6 @@
 use lasercube::{
     animation::{Animation, Frame},
-    LaserCube, LaserdockSample, XY_MAX, XY_MIN,
+    easing, LaserCube, LaserdockSample,
 };
 
 fn main() -> anyhow::Result<()> {
@@ -13,8 +13,8 @@ fn main() -> anyhow::Result<()> {
     let mut even_odd = -1.;
     for step in 0..steps {
         let step_norm = step as f64 / (steps - 1) as f64;
-        let step_eased = simple_easing::quad_out(step_norm as f32) as f64;
-        let y = y_start - (y_delta as f64 * step_eased);
+        let step_eased = easing::quad_out(step_norm as f32) as f64;
+        let y = y_start - (y_delta * step_eased);
         let mut line = vec![];
         for line_point in 0..=20 {
             let x = (-1. + (line_point as f64) / 10.) * even_odd;
@@ -27,14 +27,14 @@ fn main() -> anyhow::Result<()> {
             ));
         }
         frames.push(Frame::new(line));
-        even_odd = even_odd * -1.;
+        even_odd = -even_odd;
     }
 
     let anim = Animation::new(frames, delay_ms);
     let mut device = LaserCube::open_first()?;
     device.set_dac_rate(5000)?;
     loop {
-        anim.play(&device)?;
+        anim.play(&mut device)?;
         // frames[0].draw(&device)?;
     }
 }
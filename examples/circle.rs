@@ -1,13 +1,10 @@
-use std::{f64::consts::PI, mem::size_of};
+use std::f64::consts::PI;
 
 use anyhow::Result;
-use bytemuck::cast_slice;
-use lasercube::*;
-use lasy::{
-    euler_graph_to_euler_circuit, interpolate_euler_circuit, point_graph_to_euler_graph,
-    points_to_segments, segments_to_point_graph, InterpolationConfig,
+use lasercube::{
+    interpolation::{optimize, LaserInterpolation},
+    *,
 };
-use log::debug;
 fn main() -> Result<()> {
     pretty_env_logger::init();
 
@@ -19,9 +16,6 @@ fn main() -> Result<()> {
     // instead of dealing with a ring buffer we'll just create two circles one after another
     // and send a slice of that
 
-    const SAMPLE_SIZE: usize = size_of::<LaserdockSample>() / size_of::<u8>();
-    const SAMPLES_PER_BATCH: usize = lasercube::BYTES_PER_BATCH / SAMPLE_SIZE;
-
     let mut circle: Vec<LaserdockSample> = vec![];
 
     for i in 0..NUM_POINTS {
@@ -31,7 +25,7 @@ fn main() -> Result<()> {
         }
         circle.push(LaserdockSample::new(
             (i % 40) as u8,
-            128 as u8,
+            128,
             (i % 130) as u8,
             angle.sin(),
             angle.cos(),
@@ -51,7 +45,7 @@ fn main() -> Result<()> {
         }
         circle.push(LaserdockSample::new(
             (i % 40) as u8,
-            128 as u8,
+            128,
             (i % 130) as u8,
             angle.sin() / 2.,
             angle.cos() / 2.,
@@ -60,16 +54,11 @@ fn main() -> Result<()> {
 
     let points = circle;
 
-    let input_points = points.clone();
-    let segs = points_to_segments(&input_points);
-    let pg = segments_to_point_graph(&input_points, segs);
-    let eg = point_graph_to_euler_graph(&pg);
-    let ec = euler_graph_to_euler_circuit(&input_points, &eg);
-    let output_points: Vec<LaserdockSample> =
-        interpolate_euler_circuit(&input_points, &ec, &eg, 10, &InterpolationConfig::default());
+    let interpolation = LaserInterpolation::for_dac_rate(lc.dac_rate()?);
+    let output_points = optimize(&points, 10, &interpolation.config());
 
     loop {
-        for chunk in output_points.chunks(SAMPLES_PER_BATCH) {
+        for chunk in lc.batch_iter(&output_points) {
             lc.send_samples(chunk)?;
         }
     }
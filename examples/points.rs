@@ -1,11 +1,7 @@
-use std::{f64::consts::PI, mem::size_of};
-
 use anyhow::Result;
-use bytemuck::cast_slice;
-use lasercube::{LaserCube, LaserdockSample, SAMPLES_PER_BATCH, SAMPLE_SIZE, XY};
-use lasy::{
-    euler_graph_to_euler_circuit, interpolate_euler_circuit, point_graph_to_euler_graph,
-    points_to_segments, segments_to_point_graph, InterpolationConfig,
+use lasercube::{
+    animation::Frame, interpolation::LaserInterpolation, LaserCube, LaserdockSample,
+    SAMPLES_PER_BATCH, SAMPLE_SIZE,
 };
 use log::debug;
 fn main() -> Result<()> {
@@ -61,19 +57,10 @@ fn main() -> Result<()> {
     //         XY(p2s[p2s.len() - 1].1 * 2 + add),
     //     ));
     // }
-    let input_points = points.clone();
-    let segs = points_to_segments(&input_points);
-    let pg = segments_to_point_graph(&input_points, segs);
-    let eg = point_graph_to_euler_graph(&pg);
-    let ec = euler_graph_to_euler_circuit(&input_points, &eg);
-    let output_points: Vec<LaserdockSample> =
-        interpolate_euler_circuit(&input_points, &ec, &eg, 20, &InterpolationConfig::default());
+    let interpolation = LaserInterpolation::for_dac_rate(lc.dac_rate()?);
+    let frame = Frame::optimized(points, 20, &interpolation.config());
 
     loop {
-        for chunk in points.chunks(SAMPLES_PER_BATCH) {
-            lc.send_samples(chunk)?;
-        }
+        frame.draw(&lc)?;
     }
-
-    Ok(())
 }
@@ -1,57 +1,673 @@
-use std::{thread::sleep, time::Duration};
+use std::{
+    fs,
+    path::Path,
+    sync::Mutex,
+    thread::sleep,
+    time::{Duration, Instant},
+};
 
-use crate::{LaserCube, LaserdockSample};
+use anyhow::anyhow;
+use lasy::{Blanked, InterpolationConfig, IsBlank, Lerp};
+use log::{debug, warn};
+use rusb::UsbContext;
+
+use crate::{easing::Easing, geometry, ilda, interpolation, pipeline, LaserCube, LaserdockSample};
 
 pub struct Frame {
     points: Vec<LaserdockSample>,
+    // points past this are the transition appended by `Animation::new`
+    content_len: usize,
+    delay_ms: Option<u64>,
+    dac_rate: Option<u32>,
 }
 
 impl Frame {
+    pub const MIN_DUTY_CYCLE: f32 = 0.5;
+
     pub fn new(points: Vec<LaserdockSample>) -> Self {
-        Self { points }
+        Self {
+            content_len: points.len(),
+            points,
+            delay_ms: None,
+            dac_rate: None,
+        }
     }
 
-    pub fn draw(&self, device: &LaserCube) -> anyhow::Result<()> {
+    /// Run the lasy optimization pipeline once and keep the result. The pipeline is far too slow
+    /// to run per draw, so build frames like this up front and reuse them.
+    pub fn optimized(
+        input: Vec<LaserdockSample>,
+        density: u32,
+        config: &InterpolationConfig,
+    ) -> Self {
+        Self::new(interpolation::optimize(&input, density, config))
+    }
+
+    /// A frame held for `delay_ms` instead of the animation's default delay.
+    pub fn with_delay(points: Vec<LaserdockSample>, delay_ms: u64) -> Self {
+        Self {
+            content_len: points.len(),
+            points,
+            delay_ms: Some(delay_ms),
+            dac_rate: None,
+        }
+    }
+
+    pub fn delay_ms(&self) -> Option<u64> {
+        self.delay_ms
+    }
+
+    pub fn set_delay_ms(&mut self, delay_ms: Option<u64>) {
+        self.delay_ms = delay_ms;
+    }
+
+    pub fn dac_rate(&self) -> Option<u32> {
+        self.dac_rate
+    }
+
+    /// DAC rate `Animation::play` switches to before drawing this frame, instead of leaving the
+    /// current one.
+    pub fn set_dac_rate(&mut self, dac_rate: Option<u32>) {
+        self.dac_rate = dac_rate;
+    }
+
+    pub fn draw<T: UsbContext>(&self, device: &LaserCube<T>) -> anyhow::Result<()> {
         device.send_samples(&self.points)
     }
+
+    pub fn points(&self) -> &[LaserdockSample] {
+        &self.points
+    }
+
+    /// The frame's own points, without the transition `Animation::new` appends.
+    pub fn content(&self) -> &[LaserdockSample] {
+        &self.points[..self.content_len]
+    }
+
+    /// A copy with `f` applied to every point, keeping the frame's delay and DAC rate. The
+    /// blanked part of the transition `Animation::new` appended stays blanked, so recoloring
+    /// doesn't light up the travel to the next frame.
+    pub fn map(&self, f: impl Fn(LaserdockSample) -> LaserdockSample) -> Frame {
+        let content = self.content().iter().map(|p| f(*p));
+        let transition = self.points[self.content_len..].iter().map(|p| {
+            let mapped = f(*p);
+            if p.is_blank() {
+                mapped.blanked()
+            } else {
+                mapped
+            }
+        });
+
+        Frame {
+            points: content.chain(transition).collect(),
+            content_len: self.content_len,
+            delay_ms: self.delay_ms,
+            dac_rate: self.dac_rate,
+        }
+    }
+
+    /// A coarse copy for quick previews: keeps every `n`th lit sample, plus all blanked samples
+    /// and the ends of every lit run so the blanking boundaries stay where they were. The delay
+    /// and DAC rate carry over.
+    pub fn preview_stride(&self, n: usize) -> Frame {
+        let content = self.content();
+        let n = n.max(1);
+        let mut run_index: usize = 0;
+
+        let points = content
+            .iter()
+            .enumerate()
+            .filter(|&(i, point)| {
+                if point.is_blank() {
+                    run_index = 0;
+                    return true;
+                }
+                let run_start = run_index == 0;
+                let run_end = content.get(i + 1).is_none_or(|next| next.is_blank());
+                let keep = run_start || run_end || run_index.is_multiple_of(n);
+                run_index += 1;
+                keep
+            })
+            .map(|(_, point)| *point)
+            .collect();
+
+        let mut frame = Frame::new(points);
+        frame.delay_ms = self.delay_ms;
+        frame.dac_rate = self.dac_rate;
+        frame
+    }
+
+    /// Fraction of samples that are lit.
+    pub fn duty_cycle(&self) -> f32 {
+        if self.points.is_empty() {
+            return 0.0;
+        }
+        let lit = self.points.iter().filter(|p| !p.is_blank()).count();
+        lit as f32 / self.points.len() as f32
+    }
+
+    /// Total galvo travel in normalized units, blanked moves included.
+    pub fn path_length(&self) -> f32 {
+        self.points
+            .windows(2)
+            .map(|pair| geometry::distance(&pair[0], &pair[1]))
+            .sum()
+    }
+
+    /// Travel between consecutive lit samples only, i.e. the length of what is drawn.
+    pub fn lit_path_length(&self) -> f32 {
+        self.points
+            .windows(2)
+            .filter(|pair| !pair[0].is_blank() && !pair[1].is_blank())
+            .map(|pair| geometry::distance(&pair[0], &pair[1]))
+            .sum()
+    }
+
+    /// Warns if the frame spends most of its samples blanked, which usually means poor path
+    /// ordering. Returns whether the frame passed.
+    pub fn validate(&self) -> bool {
+        let duty_cycle = self.duty_cycle();
+        if duty_cycle < Self::MIN_DUTY_CYCLE {
+            warn!(
+                "frame is only {:.0}% lit ({} samples), consider optimizing the path",
+                duty_cycle * 100.,
+                self.points.len()
+            );
+            return false;
+        }
+        true
+    }
+
+    pub fn into_points(self) -> Vec<LaserdockSample> {
+        self.points
+    }
 }
 
+/// Recycles the point buffers of generated playback, so filling a frame doesn't allocate once
+/// the pool reaches steady state. Only these buffers are recycled: `send_samples` still allocates
+/// whenever a `RenderConfig` stage or the stream lead-in is active.
+///
+/// Starts out double-buffered: one buffer can be filled while the other is still referenced.
+pub struct FramePool {
+    free: Vec<Vec<LaserdockSample>>,
+}
+
+impl FramePool {
+    pub fn new(points_per_frame: usize) -> Self {
+        Self {
+            free: vec![
+                Vec::with_capacity(points_per_frame),
+                Vec::with_capacity(points_per_frame),
+            ],
+        }
+    }
+
+    /// An empty buffer, reusing a recycled allocation if one is available.
+    pub fn take(&mut self) -> Vec<LaserdockSample> {
+        self.free.pop().unwrap_or_default()
+    }
+
+    pub fn recycle(&mut self, mut points: Vec<LaserdockSample>) {
+        points.clear();
+        self.free.push(points);
+    }
+
+    pub fn take_frame(&mut self) -> Frame {
+        Frame::new(self.take())
+    }
+
+    pub fn recycle_frame(&mut self, frame: Frame) {
+        self.recycle(frame.into_points())
+    }
+
+    /// Fill a pooled buffer with `generate`, send it and hand the buffer back to the pool.
+    pub fn draw_with<T: UsbContext>(
+        &mut self,
+        device: &LaserCube<T>,
+        generate: impl FnOnce(&mut Vec<LaserdockSample>),
+    ) -> anyhow::Result<()> {
+        let mut points = self.take();
+        generate(&mut points);
+        let result = device.send_samples(&points);
+        self.recycle(points);
+        result
+    }
+}
+
+/// Called with the index and frame each time `play` draws a frame.
+pub type FrameCallback = Box<dyn FnMut(usize, &Frame) + Send>;
+
 pub struct Animation {
     frames: Vec<Frame>,
     delay_ms: u64,
+    speed: f32,
+    restore_dac_rate: bool,
+    on_frame: Mutex<Option<FrameCallback>>,
 }
 
 impl Animation {
+    /// Longest blanked move between frames, in normalized units; longer jumps get intermediate
+    /// blanked samples.
+    pub const MAX_BLANK_STEP: f32 = 0.25;
+
+    /// Empty frames are dropped: there is nothing to draw and no endpoint to transition from.
+    /// Single-point frames are kept and get the usual transition.
     pub fn new(mut frames: Vec<Frame>, delay_ms: u64) -> Self {
+        let before = frames.len();
+        frames.retain(|frame| !frame.content().is_empty());
+        if frames.len() < before {
+            warn!("dropping {} empty frame(s)", before - frames.len());
+        }
+        for frame in &frames {
+            frame.validate();
+        }
+
         let num_frames = frames.len();
         if num_frames > 1 {
-            for i in 0..=num_frames {
-                let next = &frames[(i + 1) % num_frames];
-                let mut next_start = next.points[0];
-                next_start.rg = 0;
-                next_start.b = 0;
+            for i in 0..num_frames {
+                let next_start = frames[(i + 1) % num_frames].points[0];
 
                 let cur = &mut frames[i % num_frames];
+                cur.points.truncate(cur.content_len);
                 let cur_end = *cur.points.last().unwrap();
-                let fixup_points = 2;
-                for _ in 0..fixup_points {
-                    cur.points.push(cur_end)
-                }
-                for _ in 0..fixup_points {
-                    cur.points.push(next_start)
-                }
+                cur.points.extend(transition(&cur_end, &next_start));
             }
         }
 
-        Self { frames, delay_ms }
+        Self {
+            frames,
+            delay_ms,
+            speed: 1.0,
+            restore_dac_rate: true,
+            on_frame: Mutex::new(None),
+        }
+    }
+
+    /// Load every `.ild` file in `dir`, sorted by file name, as one animation. Other files are
+    /// skipped; a directory without ILDA files is an error.
+    pub fn from_ilda_dir(dir: &Path, delay_ms: u64) -> anyhow::Result<Animation> {
+        let mut paths = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let is_ilda = path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("ild"));
+            if is_ilda {
+                paths.push(path);
+            } else {
+                debug!("skipping non-ILDA file {}", path.display());
+            }
+        }
+        if paths.is_empty() {
+            return Err(anyhow!("no .ild files in {}", dir.display()));
+        }
+        paths.sort();
+
+        let mut frames = vec![];
+        for path in paths {
+            frames.extend(ilda::load_frames(&path)?.into_iter().map(Frame::new));
+        }
+
+        Ok(Animation::new(frames, delay_ms))
+    }
+
+    /// Morph from `from` to `to` over `steps` frames, with `easing` shaping the progress. Points
+    /// are paired by their relative index, so both should trace the shape in the same direction.
+    pub fn morph_sequence(
+        from: &[LaserdockSample],
+        to: &[LaserdockSample],
+        steps: usize,
+        delay_ms: u64,
+        easing: Easing,
+    ) -> Animation {
+        if from.is_empty() || to.is_empty() {
+            return Animation::new(vec![], delay_ms);
+        }
+
+        let len = from.len().max(to.len());
+        let pick = |points: &[LaserdockSample], i: usize| points[i * points.len() / len];
+
+        let frames = (0..steps)
+            .map(|step| {
+                let amount = easing(step as f32 / steps.saturating_sub(1).max(1) as f32);
+                let points = (0..len)
+                    .map(|i| pick(from, i).lerp(&pick(to, i), amount))
+                    .collect();
+                Frame::new(points)
+            })
+            .collect();
+
+        Animation::new(frames, delay_ms)
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Composite `other` on top of this animation, frame by frame: each output frame draws this
+    /// animation's frame, then `other`'s, with a blanked move in between. The shorter animation
+    /// is cycled to match the longer one.
+    pub fn overlay(&self, other: &Animation) -> Animation {
+        let layers: Vec<&Animation> = vec![self, other]
+            .into_iter()
+            .filter(|animation| !animation.frames.is_empty())
+            .collect();
+        let len = layers.iter().map(|a| a.frames.len()).max().unwrap_or(0);
+
+        let frames = (0..len)
+            .map(|i| {
+                let mut points: Vec<LaserdockSample> = vec![];
+                let mut delay_ms = None;
+                let mut dac_rate = None;
+                for layer in &layers {
+                    let frame = &layer.frames[i % layer.frames.len()];
+                    if let (Some(end), Some(start)) = (points.last(), frame.content().first()) {
+                        points.extend(transition(end, start));
+                    }
+                    points.extend_from_slice(frame.content());
+                    delay_ms = delay_ms.or(frame.delay_ms);
+                    dac_rate = dac_rate.or(frame.dac_rate);
+                }
+
+                let mut frame = Frame::new(points);
+                frame.delay_ms = delay_ms;
+                frame.dac_rate = dac_rate;
+                frame
+            })
+            .collect();
+
+        let mut animation = Animation::new(frames, self.delay_ms);
+        animation.speed = self.speed;
+        animation.restore_dac_rate = self.restore_dac_rate;
+        animation
+    }
+
+    /// Play `other` after this animation. Transitions are recomputed, so the seams (including the
+    /// loop back to this animation's first frame) get blanked moves too. Frames without their own
+    /// delay keep their animation's default, so each segment keeps its timing; speed and the
+    /// DAC rate restore setting are taken from this animation.
+    pub fn chain(self, other: Animation) -> Animation {
+        let Animation {
+            mut frames,
+            delay_ms,
+            speed,
+            restore_dac_rate,
+            on_frame,
+        } = self;
+        let other_delay_ms = other.delay_ms;
+        frames.extend(other.frames.into_iter().map(|mut frame| {
+            frame.delay_ms = frame.delay_ms.or(Some(other_delay_ms));
+            frame
+        }));
+
+        let mut animation = Animation::new(frames, delay_ms);
+        animation.speed = speed;
+        animation.restore_dac_rate = restore_dac_rate;
+        animation.on_frame = on_frame;
+        animation
+    }
+
+    /// Playback speed multiplier: 2.0 halves every delay, 0.5 doubles it.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(f32::EPSILON);
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Observe playback, e.g. to drive a progress display, without changing it.
+    pub fn set_on_frame(&mut self, on_frame: Option<FrameCallback>) {
+        *self.on_frame.get_mut().unwrap() = on_frame;
+    }
+
+    fn frame_drawn(&self, index: usize, frame: &Frame) {
+        if let Some(on_frame) = self.on_frame.lock().unwrap().as_mut() {
+            on_frame(index, frame);
+        }
+    }
+
+    fn frame_delay(&self, frame: &Frame) -> Duration {
+        Duration::from_millis(frame.delay_ms.unwrap_or(self.delay_ms)).div_f32(self.speed)
+    }
+
+    /// Whether frames without their own DAC rate go back to the rate that was set before playback,
+    /// or keep whatever the previous frame switched to. Defaults to going back.
+    pub fn set_restore_dac_rate(&mut self, restore: bool) {
+        self.restore_dac_rate = restore;
+    }
+
+    pub fn restore_dac_rate(&self) -> bool {
+        self.restore_dac_rate
+    }
+
+    pub fn play<T: UsbContext>(&self, device: &mut LaserCube<T>) -> anyhow::Result<()> {
+        let mut rate = DacRateSwitch::new(self.restore_dac_rate);
+        for (i, frame) in self.frames.iter().enumerate() {
+            rate.before(device, frame)?;
+            frame.draw(device)?;
+            self.frame_drawn(i, frame);
+            sleep(self.frame_delay(frame));
+        }
+        rate.finish(device)
     }
 
-    pub fn play(&self, device: &LaserCube) -> anyhow::Result<()> {
-        let sleep_dur = Duration::from_millis(self.delay_ms);
-        for frame in &self.frames {
+    /// Like `play`, but at a fixed frame rate: the time spent sending a frame is subtracted from
+    /// the frame period instead of adding to it. Per-frame delays and `speed` are ignored.
+    pub fn play_at_fps<T: UsbContext>(
+        &self,
+        device: &mut LaserCube<T>,
+        fps: f32,
+    ) -> anyhow::Result<()> {
+        let period = Duration::from_secs_f32(1.0 / fps.max(f32::EPSILON));
+        let mut rate = DacRateSwitch::new(self.restore_dac_rate);
+        for (i, frame) in self.frames.iter().enumerate() {
+            let started = Instant::now();
+            rate.before(device, frame)?;
             frame.draw(device)?;
-            sleep(sleep_dur);
+            self.frame_drawn(i, frame);
+            match period.checked_sub(started.elapsed()) {
+                Some(remaining) => sleep(remaining),
+                None => warn!(
+                    "can't keep up with {} fps: frame took {:?}",
+                    fps,
+                    started.elapsed()
+                ),
+            }
+        }
+        rate.finish(device)
+    }
+}
+
+/// Animations played one after the other, e.g. a show built from reusable clips.
+#[derive(Default)]
+pub struct Playlist {
+    animations: Vec<Animation>,
+}
+
+impl Playlist {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, animation: Animation) {
+        self.animations.push(animation);
+    }
+
+    pub fn animations(&self) -> &[Animation] {
+        &self.animations
+    }
+
+    /// Chain everything into one animation, see `Animation::chain`. Settings come from the first
+    /// animation.
+    pub fn into_animation(self) -> Animation {
+        self.animations
+            .into_iter()
+            .reduce(Animation::chain)
+            .unwrap_or_else(|| Animation::new(vec![], 0))
+    }
+}
+
+/// DAC rate bookkeeping for `Animation::play`. Doesn't touch the device until a frame overrides
+/// the rate.
+struct DacRateSwitch {
+    restore: bool,
+    original: Option<u32>,
+    current: Option<u32>,
+}
+
+impl DacRateSwitch {
+    fn new(restore: bool) -> Self {
+        Self {
+            restore,
+            original: None,
+            current: None,
+        }
+    }
+
+    fn before<T: UsbContext>(
+        &mut self,
+        device: &mut LaserCube<T>,
+        frame: &Frame,
+    ) -> anyhow::Result<()> {
+        let target = match (frame.dac_rate, self.original) {
+            (Some(rate), _) => rate,
+            (None, Some(original)) if self.restore => original,
+            _ => return Ok(()),
+        };
+
+        if self.original.is_none() {
+            self.original = Some(device.dac_rate()?);
+            self.current = self.original;
+        }
+        if self.current != Some(target) {
+            device.set_dac_rate(target)?;
+            self.current = Some(target);
+        }
+        Ok(())
+    }
+
+    fn finish<T: UsbContext>(&mut self, device: &mut LaserCube<T>) -> anyhow::Result<()> {
+        match self.original {
+            Some(original) if self.restore && self.current != Some(original) => {
+                device.set_dac_rate(original)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Copies of `points` dimming to black over `fade_frames` frames, the last one fully dark. Play
+/// them after a loop for a soft ending instead of a hard cut.
+pub fn with_fadeout(points: &[LaserdockSample], fade_frames: usize) -> Vec<Vec<LaserdockSample>> {
+    (1..=fade_frames)
+        .map(|i| {
+            let gain = 1. - i as f32 / fade_frames as f32;
+            points
+                .iter()
+                .map(|p| {
+                    let (r, g, b) = p.rgb();
+                    let scale = |c| pipeline::scale_channel(c, gain).0;
+                    p.with_rgb(scale(r), scale(g), scale(b))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// A frame scheduled at an offset from the start of a `Timeline`.
+pub struct TimedFrame {
+    pub frame: Frame,
+    pub at: Duration,
+}
+
+/// Cue-based playback: frames are shown at fixed offsets from the start, e.g. to follow external
+/// timecode, instead of after a fixed delay.
+pub struct Timeline {
+    frames: Vec<TimedFrame>,
+}
+
+impl Timeline {
+    pub fn new(mut frames: Vec<TimedFrame>) -> Self {
+        frames.sort_by_key(|timed| timed.at);
+        Self { frames }
+    }
+
+    pub fn play<T: UsbContext>(&self, device: &LaserCube<T>) -> anyhow::Result<()> {
+        self.play_from(device, Instant::now())
+    }
+
+    /// Play with offsets relative to `start`. Frames whose successor is already due are skipped,
+    /// the others are redrawn until the next cue.
+    pub fn play_from<T: UsbContext>(
+        &self,
+        device: &LaserCube<T>,
+        start: Instant,
+    ) -> anyhow::Result<()> {
+        for (i, timed) in self.frames.iter().enumerate() {
+            let next_due = self.frames.get(i + 1).map(|next| start + next.at);
+            if next_due.is_some_and(|due| Instant::now() >= due) {
+                debug!("timeline: skipping past-due frame at {:?}", timed.at);
+                continue;
+            }
+
+            let due = start + timed.at;
+            let now = Instant::now();
+            if due > now {
+                sleep(due - now);
+            }
+
+            match next_due {
+                Some(next_due) => {
+                    while Instant::now() < next_due {
+                        timed.frame.draw(device)?;
+                    }
+                }
+                None => timed.frame.draw(device)?,
+            }
         }
         Ok(())
     }
 }
+
+/// How frame `a` hands over to frame `b`, see `frame_diff`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrameDiff {
+    /// Distance (normalized units) from `a`'s last lit sample to `b`'s first lit sample, `None`
+    /// if either frame has no lit samples.
+    pub lit_gap: Option<f32>,
+    /// Whether the jump from `a`'s last sample to `b`'s first sample is dark: both ends blanked,
+    /// or no travel at all.
+    pub blanked: bool,
+}
+
+/// Quantify the transition between two consecutive frames, to track down stray lines.
+pub fn frame_diff(a: &Frame, b: &Frame) -> FrameDiff {
+    let a_last_lit = a.points.iter().rev().find(|p| !p.is_blank());
+    let b_first_lit = b.points.iter().find(|p| !p.is_blank());
+    let lit_gap = match (a_last_lit, b_first_lit) {
+        (Some(end), Some(start)) => Some(geometry::distance(end, start)),
+        _ => None,
+    };
+
+    let blanked = match (a.points.last(), b.points.first()) {
+        (Some(end), Some(start)) => {
+            (end.is_blank() && start.is_blank()) || end.same_position(start)
+        }
+        _ => true,
+    };
+
+    FrameDiff { lit_gap, blanked }
+}
+
+/// Dwell on the end of a path, then travel blanked to the start of the next one, in steps of at
+/// most `Animation::MAX_BLANK_STEP` so long jumps stay slow enough to stay dark.
+fn transition(end: &LaserdockSample, next_start: &LaserdockSample) -> Vec<LaserdockSample> {
+    let (from, to) = (end.blanked(), next_start.blanked());
+    let steps = (geometry::distance(end, next_start) / Animation::MAX_BLANK_STEP).ceil() as usize;
+
+    let mut points = vec![*end, *end];
+    points.extend((1..steps).map(|step| from.lerp(&to, step as f32 / steps as f32)));
+    points.extend([to, to]);
+    points
+}
@@ -0,0 +1,207 @@
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use lasy::Position;
+use rusb::UsbContext;
+
+use crate::{geometry::blank_bracketed, LaserCube, LaserdockSample};
+
+/// A rectangle of the scan field in normalized coordinates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Region {
+    pub x_min: f32,
+    pub y_min: f32,
+    pub x_max: f32,
+    pub y_max: f32,
+}
+
+impl Region {
+    pub const FULL: Region = Region {
+        x_min: -1.,
+        y_min: -1.,
+        x_max: 1.,
+        y_max: 1.,
+    };
+
+    /// Map a sample from the full scan field into this region.
+    pub fn map(&self, sample: &LaserdockSample) -> LaserdockSample {
+        let [x, y] = sample.position();
+        sample.with_position(
+            self.x_min + (x + 1.) / 2. * (self.x_max - self.x_min),
+            self.y_min + (y + 1.) / 2. * (self.y_max - self.y_min),
+        )
+    }
+}
+
+/// Several independent images sharing the beam: each is drawn into its own region, one after
+/// another, with blanked moves in between.
+#[derive(Clone, Debug, Default)]
+pub struct Compositor {
+    layers: Vec<(Region, Vec<LaserdockSample>)>,
+}
+
+impl Compositor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an image authored for the full scan field, to be shown inside `region`.
+    pub fn add(&mut self, region: Region, points: Vec<LaserdockSample>) {
+        self.layers.push((region, points));
+    }
+
+    pub fn render(&self) -> Vec<LaserdockSample> {
+        self.layers
+            .iter()
+            .flat_map(|(region, points)| {
+                blank_bracketed(points.iter().map(|p| region.map(p)).collect())
+            })
+            .collect()
+    }
+}
+
+/// Placement and tint of a `Node`: scaled, then rotated (radians, counter-clockwise), then
+/// translated, all around the origin of the scan field. `tint` multiplies each color channel.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub translate: (f32, f32),
+    pub rotate: f32,
+    pub scale: (f32, f32),
+    pub tint: (f32, f32, f32),
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translate: (0., 0.),
+            rotate: 0.,
+            scale: (1., 1.),
+            tint: (1., 1., 1.),
+        }
+    }
+}
+
+impl Transform {
+    pub fn apply(&self, sample: &LaserdockSample) -> LaserdockSample {
+        let [x, y] = sample.position();
+        let (x, y) = (x * self.scale.0, y * self.scale.1);
+        let (sin, cos) = self.rotate.sin_cos();
+        let (x, y) = (x * cos - y * sin, x * sin + y * cos);
+
+        let (r, g, b) = sample.rgb();
+        let tint = |c: u8, gain: f32| (c as f32 * gain).round().clamp(0., 255.) as u8;
+        sample
+            .with_position(x + self.translate.0, y + self.translate.1)
+            .with_rgb(
+                tint(r, self.tint.0),
+                tint(g, self.tint.1),
+                tint(b, self.tint.2),
+            )
+    }
+}
+
+/// A drawing placed in a `Scene`.
+#[derive(Clone, Debug, Default)]
+pub struct Node {
+    pub points: Vec<LaserdockSample>,
+    pub transform: Transform,
+}
+
+impl Node {
+    pub fn new(points: Vec<LaserdockSample>, transform: Transform) -> Self {
+        Self { points, transform }
+    }
+}
+
+/// Retained-mode composition: nodes are transformed and drawn in insertion order, with blanked
+/// moves in between.
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    nodes: Vec<Node>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, node: Node) {
+        self.nodes.push(node);
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn nodes_mut(&mut self) -> &mut [Node] {
+        &mut self.nodes
+    }
+
+    pub fn render(&self) -> Vec<LaserdockSample> {
+        self.nodes
+            .iter()
+            .flat_map(|node| {
+                blank_bracketed(
+                    node.points
+                        .iter()
+                        .map(|p| node.transform.apply(p))
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Time multiplexing: whole images take turns on the beam, switching fast enough that persistence
+/// of vision shows them all at once. Unlike `Compositor`, every image keeps the full scan field.
+#[derive(Clone, Debug)]
+pub struct Multiplexer {
+    images: Vec<Vec<LaserdockSample>>,
+    next: usize,
+    switch_rate: f32,
+}
+
+impl Multiplexer {
+    /// `switch_rate` is in images per second.
+    pub fn new(images: Vec<Vec<LaserdockSample>>, switch_rate: f32) -> Self {
+        Self {
+            images,
+            next: 0,
+            switch_rate: switch_rate.max(f32::EPSILON),
+        }
+    }
+
+    pub fn set_switch_rate(&mut self, switch_rate: f32) {
+        self.switch_rate = switch_rate.max(f32::EPSILON);
+    }
+
+    pub fn switch_rate(&self) -> f32 {
+        self.switch_rate
+    }
+
+    /// Send the next image in turn.
+    pub fn tick<T: UsbContext>(&mut self, device: &LaserCube<T>) -> Result<()> {
+        if self.images.is_empty() {
+            return Ok(());
+        }
+        let image = &self.images[self.next];
+        self.next = (self.next + 1) % self.images.len();
+        device.send_samples(image)
+    }
+
+    /// Tick at `switch_rate` for `duration`.
+    pub fn run<T: UsbContext>(&mut self, device: &LaserCube<T>, duration: Duration) -> Result<()> {
+        let period = Duration::from_secs_f32(1. / self.switch_rate);
+        let start = Instant::now();
+        let mut due = start;
+        while due.duration_since(start) < duration {
+            self.tick(device)?;
+            due += period;
+            sleep(due.saturating_duration_since(Instant::now()));
+        }
+        Ok(())
+    }
+}
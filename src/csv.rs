@@ -0,0 +1,79 @@
+use std::{fmt::Write as _, fs, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use lasy::Position;
+
+use crate::LaserdockSample;
+
+/// Read `x,y,r,g,b` rows: coordinates in -1..1, colors 0..255. Fields may be separated by commas,
+/// tabs or spaces. Blank lines and everything after a `#` are ignored. Coordinates outside the
+/// scan field are an error rather than being clamped.
+pub fn load_points_csv(path: &Path) -> Result<Vec<LaserdockSample>> {
+    let text = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    parse_points_csv(&text).with_context(|| format!("parsing {}", path.display()))
+}
+
+pub fn parse_points_csv(text: &str) -> Result<Vec<LaserdockSample>> {
+    let mut points = vec![];
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|field| !field.is_empty())
+            .collect();
+        let [x, y, r, g, b] = fields[..] else {
+            return Err(anyhow!(
+                "line {}: expected 5 fields (x,y,r,g,b), got {}",
+                i + 1,
+                fields.len()
+            ));
+        };
+
+        let coord = |s: &str| {
+            let value = s
+                .parse::<f64>()
+                .with_context(|| format!("line {}: bad coordinate {:?}", i + 1, s))?;
+            if !(-1.0..=1.0).contains(&value) {
+                return Err(anyhow!("line {}: coordinate {} outside -1..1", i + 1, s));
+            }
+            Ok(value)
+        };
+        let color = |s: &str| {
+            s.parse::<u8>()
+                .with_context(|| format!("line {}: bad color {:?}", i + 1, s))
+        };
+        points.push(LaserdockSample::new(
+            color(r)?,
+            color(g)?,
+            color(b)?,
+            coord(x)?,
+            coord(y)?,
+        ));
+    }
+
+    Ok(points)
+}
+
+pub(crate) const HEADER: &str = "# x,y,r,g,b\n";
+
+pub(crate) fn write_rows(text: &mut String, points: &[LaserdockSample]) {
+    for point in points {
+        let [x, y] = point.position();
+        let (r, g, b) = point.rgb();
+        // writing to a String can't fail
+        let _ = writeln!(text, "{},{},{},{},{}", x, y, r, g, b);
+    }
+}
+
+/// Write samples in the format `load_points_csv` reads.
+pub fn save_points_csv(path: &Path, points: &[LaserdockSample]) -> Result<()> {
+    let mut text = String::from(HEADER);
+    write_rows(&mut text, points);
+
+    fs::write(path, text).with_context(|| format!("writing {}", path.display()))
+}
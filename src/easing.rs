@@ -0,0 +1,37 @@
+//! Easing curves mapping linear progress in 0..1 to eased progress in 0..1.
+
+use std::f32::consts::PI;
+
+pub type Easing = fn(f32) -> f32;
+
+pub fn linear(t: f32) -> f32 {
+    t
+}
+
+pub fn quad_in(t: f32) -> f32 {
+    t * t
+}
+
+pub fn quad_out(t: f32) -> f32 {
+    1. - (1. - t) * (1. - t)
+}
+
+pub fn quad_in_out(t: f32) -> f32 {
+    if t < 0.5 {
+        2. * t * t
+    } else {
+        1. - (-2. * t + 2.).powi(2) / 2.
+    }
+}
+
+pub fn cubic_in_out(t: f32) -> f32 {
+    if t < 0.5 {
+        4. * t * t * t
+    } else {
+        1. - (-2. * t + 2.).powi(3) / 2.
+    }
+}
+
+pub fn sine_in_out(t: f32) -> f32 {
+    -((PI * t).cos() - 1.) / 2.
+}
@@ -0,0 +1,359 @@
+use std::f64::consts::PI;
+
+use lasy::{Blanked, IsBlank, Lerp, Position};
+
+use crate::LaserdockSample;
+
+/// Lissajous figure `x = sin(a·t + delta)`, `y = sin(b·t)` over one period, closed when `a` and
+/// `b` are integers.
+pub fn lissajous(
+    a: f64,
+    b: f64,
+    delta: f64,
+    points: usize,
+    color: (u8, u8, u8),
+) -> Vec<LaserdockSample> {
+    let (r, g, bl) = color;
+    let lit = (0..=points)
+        .map(|i| {
+            let t = i as f64 / points.max(1) as f64 * PI * 2.;
+            LaserdockSample::new(r, g, bl, (a * t + delta).sin(), (b * t).sin())
+        })
+        .collect();
+
+    blank_bracketed(lit)
+}
+
+/// Arc of the circle around `center` from `start_angle` to `end_angle` (radians, counter-clockwise
+/// from the positive X axis), drawn with `points` lit samples, at least two. The first and last
+/// samples land exactly on the requested angles; `0..2π` gives a full circle.
+pub fn arc(
+    center: (f64, f64),
+    radius: f64,
+    start_angle: f64,
+    end_angle: f64,
+    color: (u8, u8, u8),
+    points: usize,
+) -> Vec<LaserdockSample> {
+    let (r, g, b) = color;
+    let steps = points.max(2) - 1;
+    let lit = (0..=steps)
+        .map(|i| {
+            let angle = start_angle + (end_angle - start_angle) * i as f64 / steps as f64;
+            LaserdockSample::new(
+                r,
+                g,
+                b,
+                center.0 + radius * angle.cos(),
+                center.1 + radius * angle.sin(),
+            )
+        })
+        .collect();
+
+    blank_bracketed(lit)
+}
+
+/// Straight line from `start` to `end` whose color fades from `color_start` to `color_end` over
+/// `points` lit samples.
+pub fn gradient_line(
+    start: (f32, f32),
+    end: (f32, f32),
+    color_start: (u8, u8, u8),
+    color_end: (u8, u8, u8),
+    points: usize,
+) -> Vec<LaserdockSample> {
+    let (r0, g0, b0) = color_start;
+    let (r1, g1, b1) = color_end;
+    let from = LaserdockSample::new(r0, g0, b0, start.0 as f64, start.1 as f64);
+    let to = LaserdockSample::new(r1, g1, b1, end.0 as f64, end.1 as f64);
+
+    let steps = points.saturating_sub(1).max(1);
+    let lit = (0..points)
+        .map(|i| from.lerp(&to, i as f32 / steps as f32))
+        .collect();
+
+    blank_bracketed(lit)
+}
+
+/// Plot the curve `f(t)` for `points` values of `t` spread evenly over `t_range`, both ends
+/// included. Coordinates outside the scan field are clamped to its edge.
+pub fn parametric(
+    f: impl Fn(f32) -> (f32, f32),
+    t_range: (f32, f32),
+    points: usize,
+    color: (u8, u8, u8),
+) -> Vec<LaserdockSample> {
+    let (t_start, t_end) = t_range;
+    let (r, g, b) = color;
+    let steps = points.saturating_sub(1).max(1);
+    let lit = (0..points)
+        .map(|i| {
+            let (x, y) = f(t_start + (t_end - t_start) * i as f32 / steps as f32);
+            LaserdockSample::new(r, g, b, x as f64, y as f64)
+        })
+        .collect();
+
+    blank_bracketed(lit)
+}
+
+/// Hypotrochoid traced by a `wheel`-tooth gear rolling inside a `ring`-tooth gear, with the pen
+/// `pen` teeth from the wheel's center. Scaled to fill the scan field.
+pub fn spirograph(
+    ring: u32,
+    wheel: u32,
+    pen: f64,
+    points: usize,
+    color: (u8, u8, u8),
+) -> Vec<LaserdockSample> {
+    let (r, g, b) = color;
+    let ring = ring as f64;
+    let wheel = wheel.max(1) as f64;
+    let rolling = ring - wheel;
+    let extent = rolling.abs() + pen.abs();
+    let scale = if extent > 0. { 1. / extent } else { 0. };
+    // the curve closes after the wheel has turned lcm(ring, wheel) / ring times around the ring
+    let turns = wheel / gcd(ring as u64, wheel as u64).max(1) as f64;
+
+    let lit = (0..=points)
+        .map(|i| {
+            let t = i as f64 / points.max(1) as f64 * PI * 2. * turns;
+            let x = rolling * t.cos() + pen * (rolling / wheel * t).cos();
+            let y = rolling * t.sin() - pen * (rolling / wheel * t).sin();
+            LaserdockSample::new(r, g, b, x * scale, y * scale)
+        })
+        .collect();
+
+    blank_bracketed(lit)
+}
+
+/// Raster-scan a grid of colors like a CRT: rows top to bottom, each row left to right with one
+/// sample per cell centre. `bounds` is `(x_min, y_min, x_max, y_max)` in normalized coordinates.
+/// The horizontal retrace and the vertical flyback are blanked.
+pub fn raster_scan(
+    grid: &[Vec<(u8, u8, u8)>],
+    bounds: (f32, f32, f32, f32),
+) -> Vec<LaserdockSample> {
+    let (x_min, y_min, x_max, y_max) = bounds;
+    let rows = grid.len();
+    let mut points = vec![];
+
+    for (row, cells) in grid.iter().enumerate() {
+        let y = y_max - (row as f32 + 0.5) * (y_max - y_min) / rows as f32;
+        let lit = cells.iter().enumerate().map(|(col, &(r, g, b))| {
+            let x = x_min + (col as f32 + 0.5) * (x_max - x_min) / cells.len() as f32;
+            LaserdockSample::new(r, g, b, x as f64, y as f64)
+        });
+        points.extend(blank_bracketed(lit.collect()));
+    }
+
+    points
+}
+
+/// Insert interpolated lit samples between consecutive lit samples so that no lit move is longer
+/// than `max_step` in normalized (-1..1) space. Blanked moves are left alone.
+pub fn densify(points: &[LaserdockSample], max_step: f32) -> Vec<LaserdockSample> {
+    subdivide(points, max_step, false)
+}
+
+/// Hard cap on galvo velocity: wherever two consecutive samples are more than `max_step` apart
+/// (normalized space), insert intermediate samples. Moves between lit samples get lit
+/// intermediates, any move touching a blanked sample gets blanked ones. Meant as the last stage
+/// before sending.
+pub fn limit_velocity(points: &[LaserdockSample], max_step: f32) -> Vec<LaserdockSample> {
+    subdivide(points, max_step, true)
+}
+
+/// Fake a thicker stroke: every run of lit samples is drawn `passes` times, offset perpendicular
+/// to the path by up to `width / 2` to either side, with a blanked move between passes. Blanked
+/// samples are kept as they are.
+pub fn thicken(points: &[LaserdockSample], width: f32, passes: usize) -> Vec<LaserdockSample> {
+    let passes = passes.max(1);
+    let mut thick = Vec::with_capacity(points.len() * passes);
+    let mut start = 0;
+
+    while start < points.len() {
+        if points[start].is_blank() {
+            thick.push(points[start]);
+            start += 1;
+            continue;
+        }
+
+        let end = points[start..]
+            .iter()
+            .position(|p| p.is_blank())
+            .map_or(points.len(), |len| start + len);
+        for pass in 0..passes {
+            let offset = if passes == 1 {
+                0.
+            } else {
+                width * (pass as f32 / (passes - 1) as f32 - 0.5)
+            };
+            let mut pass_points = offset_path(&points[start..end], offset);
+            // alternate direction so the blanked move between passes stays short
+            if pass % 2 == 1 {
+                pass_points.reverse();
+            }
+            thick.extend(blank_bracketed(pass_points));
+        }
+        start = end;
+    }
+
+    thick
+}
+
+/// Shift every sample of a lit run sideways by `offset`, along the normal of the local direction.
+fn offset_path(run: &[LaserdockSample], offset: f32) -> Vec<LaserdockSample> {
+    run.iter()
+        .enumerate()
+        .map(|(i, point)| {
+            let [px, py] = run[i.saturating_sub(1)].position();
+            let [nx, ny] = run[(i + 1).min(run.len() - 1)].position();
+            let (dx, dy) = (nx - px, ny - py);
+            let len = (dx * dx + dy * dy).sqrt();
+            if len == 0. {
+                return *point;
+            }
+
+            let [x, y] = point.position();
+            point.with_position(x - dy / len * offset, y + dx / len * offset)
+        })
+        .collect()
+}
+
+/// Resample to exactly `target` samples spread evenly over the original sequence, so every frame
+/// of a show can have the same refresh rate. Lit stretches are interpolated, moves touching a
+/// blanked sample take the nearest original sample. Downsampling can drop corners and short
+/// blanked moves, so keep the reduction modest.
+pub fn normalize_point_count(points: &[LaserdockSample], target: usize) -> Vec<LaserdockSample> {
+    if points.len() < 2 || target < 2 {
+        return points.iter().cycle().take(target).copied().collect();
+    }
+
+    let scale = (points.len() - 1) as f32 / (target - 1) as f32;
+    (0..target)
+        .map(|i| {
+            let t = i as f32 * scale;
+            let a = (t as usize).min(points.len() - 2);
+            let (from, to) = (&points[a], &points[a + 1]);
+            let amount = t - a as f32;
+            if from.is_blank() || to.is_blank() {
+                if amount < 0.5 {
+                    *from
+                } else {
+                    *to
+                }
+            } else {
+                from.lerp(to, amount)
+            }
+        })
+        .collect()
+}
+
+/// N-fold rotational symmetry: `segments` copies of `points` rotated around the origin in steps
+/// of `2π / segments`, joined by blanked moves.
+pub fn kaleidoscope(points: &[LaserdockSample], segments: usize) -> Vec<LaserdockSample> {
+    symmetric_copies(points, segments, false)
+}
+
+/// Like `kaleidoscope`, but every other copy is mirrored as well, like the mirrors of a real
+/// kaleidoscope.
+pub fn kaleidoscope_mirrored(points: &[LaserdockSample], segments: usize) -> Vec<LaserdockSample> {
+    symmetric_copies(points, segments, true)
+}
+
+fn symmetric_copies(
+    points: &[LaserdockSample],
+    segments: usize,
+    mirror_alternate: bool,
+) -> Vec<LaserdockSample> {
+    (0..segments)
+        .flat_map(|segment| {
+            let (sin, cos) = (segment as f32 * 2. * PI as f32 / segments as f32).sin_cos();
+            let mirror = mirror_alternate && segment % 2 == 1;
+            let copy = points
+                .iter()
+                .map(|p| {
+                    let [x, y] = p.position();
+                    let y = if mirror { -y } else { y };
+                    p.with_position(x * cos - y * sin, x * sin + y * cos)
+                })
+                .collect();
+            blank_bracketed(copy)
+        })
+        .collect()
+}
+
+/// Collapse runs of identical adjacent samples to a single sample.
+pub fn dedup_consecutive(points: &[LaserdockSample]) -> Vec<LaserdockSample> {
+    dedup_consecutive_keep(points, 1)
+}
+
+/// Like `dedup_consecutive`, but keep up to `keep` samples of every run, to preserve intentional
+/// dwell points.
+pub fn dedup_consecutive_keep(points: &[LaserdockSample], keep: usize) -> Vec<LaserdockSample> {
+    let keep = keep.max(1);
+    let mut deduped: Vec<LaserdockSample> = Vec::with_capacity(points.len());
+    let mut run = 0;
+
+    for point in points {
+        if deduped.last() == Some(point) {
+            run += 1;
+        } else {
+            run = 1;
+        }
+        if run <= keep {
+            deduped.push(*point);
+        }
+    }
+
+    deduped
+}
+
+fn subdivide(points: &[LaserdockSample], max_step: f32, blank_moves: bool) -> Vec<LaserdockSample> {
+    let mut dense = Vec::with_capacity(points.len());
+
+    for (i, point) in points.iter().enumerate() {
+        if let Some(prev) = i.checked_sub(1).map(|prev| &points[prev]) {
+            let lit = !prev.is_blank() && !point.is_blank();
+            if max_step > 0. && (lit || blank_moves) {
+                let steps = (distance(prev, point) / max_step).ceil() as usize;
+                for step in 1..steps {
+                    let sample = prev.lerp(point, step as f32 / steps as f32);
+                    dense.push(if lit { sample } else { sample.blanked() });
+                }
+            }
+        }
+        dense.push(*point);
+    }
+
+    dense
+}
+
+pub(crate) fn distance(a: &LaserdockSample, b: &LaserdockSample) -> f32 {
+    let [ax, ay] = a.position();
+    let [bx, by] = b.position();
+    ((bx - ax).powi(2) + (by - ay).powi(2)).sqrt()
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Surround a lit path with blanked samples at its first and last position, so the beam travels
+/// dark into and out of it.
+pub fn blank_bracketed(lit: Vec<LaserdockSample>) -> Vec<LaserdockSample> {
+    let (first, last) = match (lit.first(), lit.last()) {
+        (Some(first), Some(last)) => (first.blanked(), last.blanked()),
+        _ => return lit,
+    };
+
+    let mut points = Vec::with_capacity(lit.len() + 2);
+    points.push(first);
+    points.extend(lit);
+    points.push(last);
+    points
+}
@@ -0,0 +1,99 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use thiserror::Error;
+
+use crate::LaserdockSample;
+
+#[derive(Error, Debug)]
+pub enum IldaError {
+    #[error("not an ILDA file: bad magic at offset {0}")]
+    BadMagic(usize),
+
+    #[error("truncated ILDA data at offset {0}")]
+    Truncated(usize),
+
+    #[error("unsupported ILDA format code {0}")]
+    UnsupportedFormat(u8),
+}
+
+const HEADER_LEN: usize = 32;
+const BLANKING_BIT: u8 = 0x40;
+
+/// Read every frame of an ILDA file.
+pub fn load_frames(path: &Path) -> Result<Vec<Vec<LaserdockSample>>> {
+    let bytes = fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    parse(&bytes).with_context(|| format!("parsing {}", path.display()))
+}
+
+/// Parse ILDA formats 0, 1 (indexed), 2 (palette), 4 and 5 (true color). Indexed points use the
+/// most recent palette section; files without one are drawn white.
+pub fn parse(bytes: &[u8]) -> Result<Vec<Vec<LaserdockSample>>, IldaError> {
+    let mut frames = vec![];
+    let mut palette: Vec<(u8, u8, u8)> = vec![];
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let header = bytes
+            .get(offset..offset + HEADER_LEN)
+            .ok_or(IldaError::Truncated(offset))?;
+        if &header[0..4] != b"ILDA" {
+            return Err(IldaError::BadMagic(offset));
+        }
+        let format = header[7];
+        let records = u16::from_be_bytes([header[24], header[25]]) as usize;
+        offset += HEADER_LEN;
+
+        // a header without records marks the end of the file
+        if records == 0 {
+            break;
+        }
+
+        let record_len = match format {
+            0 => 8,
+            1 => 6,
+            2 => 3,
+            4 => 10,
+            5 => 8,
+            format => return Err(IldaError::UnsupportedFormat(format)),
+        };
+        let data = bytes
+            .get(offset..offset + records * record_len)
+            .ok_or(IldaError::Truncated(offset))?;
+        offset += records * record_len;
+
+        if format == 2 {
+            palette = data.chunks(3).map(|c| (c[0], c[1], c[2])).collect();
+            continue;
+        }
+
+        let indexed = |index: u8| {
+            palette
+                .get(index as usize)
+                .copied()
+                .unwrap_or((255, 255, 255))
+        };
+        let frame = data
+            .chunks(record_len)
+            .map(|rec| {
+                let x = i16::from_be_bytes([rec[0], rec[1]]) as f64 / i16::MAX as f64;
+                let y = i16::from_be_bytes([rec[2], rec[3]]) as f64 / i16::MAX as f64;
+                let (status, (r, g, b)) = match format {
+                    0 => (rec[6], indexed(rec[7])),
+                    1 => (rec[4], indexed(rec[5])),
+                    4 => (rec[6], (rec[9], rec[8], rec[7])),
+                    _ => (rec[4], (rec[7], rec[6], rec[5])),
+                };
+
+                if status & BLANKING_BIT != 0 {
+                    LaserdockSample::new(0, 0, 0, x, y)
+                } else {
+                    LaserdockSample::new(r, g, b, x, y)
+                }
+            })
+            .collect();
+        frames.push(frame);
+    }
+
+    Ok(frames)
+}
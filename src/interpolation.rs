@@ -0,0 +1,101 @@
+use lasy::{
+    euler_circuit_to_segments, euler_graph_to_euler_circuit, interpolate_euler_circuit,
+    point_graph_to_euler_graph, points_to_segments, segments_to_point_graph, EulerCircuit,
+    EulerGraph, InterpolationConfig, Position, SegmentKind,
+};
+
+use crate::LaserdockSample;
+
+/// Interpolation parameters for the lasy pipeline, expressed in terms of the DAC rate.
+///
+/// lasy counts every delay in points, but galvos and modulators respond in wall-clock time, so
+/// the right number of points depends on how fast the DAC consumes them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LaserInterpolation {
+    /// Points per unit of lit travel (lasy multiplies segment length by this).
+    pub distance_per_point: f32,
+    /// Blanked dwell points at the end of each blank jump.
+    pub blank_delay_points: u32,
+    /// Corner angle in radians that earns one extra dwell point.
+    pub radians_per_point: f32,
+}
+
+impl LaserInterpolation {
+    /// The rate lasy's own defaults are tuned for.
+    pub const REFERENCE_DAC_RATE: u32 = 10_000;
+
+    pub fn for_dac_rate(rate: u32) -> Self {
+        let scale = rate.max(1) as f32 / Self::REFERENCE_DAC_RATE as f32;
+
+        let blank_delay_points =
+            (InterpolationConfig::DEFAULT_BLANK_DELAY_POINTS as f32 * scale).round() as u32;
+
+        LaserInterpolation {
+            distance_per_point: InterpolationConfig::DEFAULT_DISTANCE_PER_POINT * scale,
+            blank_delay_points: blank_delay_points.max(1),
+            radians_per_point: InterpolationConfig::DEFAULT_RADIANS_PER_POINT / scale,
+        }
+    }
+
+    pub fn config(&self) -> InterpolationConfig {
+        InterpolationConfig {
+            distance_per_point: self.distance_per_point,
+            blank_delay_points: self.blank_delay_points,
+            radians_per_point: self.radians_per_point,
+        }
+    }
+}
+
+impl Default for LaserInterpolation {
+    fn default() -> Self {
+        Self::for_dac_rate(Self::REFERENCE_DAC_RATE)
+    }
+}
+
+impl From<LaserInterpolation> for InterpolationConfig {
+    fn from(interpolation: LaserInterpolation) -> Self {
+        interpolation.config()
+    }
+}
+
+/// Run the full lasy pipeline (segments, euler circuit, interpolation) over `points`, producing
+/// at least `target_points` samples in an optimized draw order.
+pub fn optimize(
+    points: &[LaserdockSample],
+    target_points: u32,
+    config: &InterpolationConfig,
+) -> Vec<LaserdockSample> {
+    let (eg, ec) = euler_circuit(points);
+    interpolate_euler_circuit(points, &ec, &eg, target_points.max(1), config)
+}
+
+/// Run the lasy pipeline with a point budget derived from the drawn path: one point per
+/// `spacing` of lit travel (in normalized units). lasy spreads the budget proportionally to
+/// segment length, so long and short segments end up equally dense.
+pub fn interpolate_by_spacing(
+    points: &[LaserdockSample],
+    spacing: f32,
+    config: &InterpolationConfig,
+) -> Vec<LaserdockSample> {
+    let (eg, ec) = euler_circuit(points);
+
+    let lit_length: f32 = euler_circuit_to_segments(&ec, &eg)
+        .filter(|segment| segment.kind == SegmentKind::Lit)
+        .map(|segment| {
+            let [ax, ay] = points[segment.start as usize].position();
+            let [bx, by] = points[segment.end as usize].position();
+            ((bx - ax).powi(2) + (by - ay).powi(2)).sqrt()
+        })
+        .sum();
+    let target_points = (lit_length / spacing.max(f32::EPSILON)).ceil() as u32;
+
+    interpolate_euler_circuit(points, &ec, &eg, target_points.max(1), config)
+}
+
+fn euler_circuit(points: &[LaserdockSample]) -> (EulerGraph, EulerCircuit) {
+    let segments = points_to_segments(points);
+    let pg = segments_to_point_graph(points, segments);
+    let eg = point_graph_to_euler_graph(&pg);
+    let ec = euler_graph_to_euler_circuit(points, &eg);
+    (eg, ec)
+}
@@ -0,0 +1,60 @@
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use rusb::UsbContext;
+
+use crate::{LaserCube, LaserdockSample, SAMPLES_PER_BATCH};
+
+/// Streaming that keeps at most `max_latency` worth of samples queued on the device, so new
+/// content shows up quickly at the risk of an underrun when the generator falls behind.
+///
+/// libLaserdock names register 0x89 a ring buffer sample count, but what it reports is
+/// unverified, so the queue depth is estimated with a timing model instead: every sent sample is
+/// assumed to take `1 / dac_rate` to scan, starting when it was sent or when the previously
+/// queued samples run out, whichever is later.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LatencyBudget {
+    pub max_latency: Duration,
+}
+
+impl LatencyBudget {
+    pub fn new(max_latency: Duration) -> Self {
+        Self { max_latency }
+    }
+
+    /// Samples that fit in the budget at `dac_rate`, at least one batch.
+    pub fn max_samples(&self, dac_rate: u32) -> usize {
+        ((self.max_latency.as_secs_f64() * dac_rate as f64) as usize).max(SAMPLES_PER_BATCH)
+    }
+
+    /// Send frames from `next_frame` until it returns `None`, one batch at a time, waiting
+    /// before each batch until it fits in the budget.
+    pub fn stream<T: UsbContext>(
+        &self,
+        device: &LaserCube<T>,
+        mut next_frame: impl FnMut() -> Option<Vec<LaserdockSample>>,
+    ) -> Result<()> {
+        let rate = device.dac_rate()?.max(1);
+        let scan_time = |samples: usize| Duration::from_secs_f64(samples as f64 / rate as f64);
+        let budget = scan_time(self.max_samples(rate));
+        let mut queued_until = Instant::now();
+
+        while let Some(frame) = next_frame() {
+            for batch in frame.chunks(SAMPLES_PER_BATCH) {
+                let batch_time = scan_time(batch.len());
+                let queued = queued_until.saturating_duration_since(Instant::now());
+                if let Some(excess) = (queued + batch_time).checked_sub(budget) {
+                    sleep(excess);
+                }
+
+                device.send_samples(batch)?;
+                queued_until = queued_until.max(Instant::now()) + batch_time;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,25 +1,57 @@
 use std::{
+    borrow::Cow,
     convert::TryInto,
     mem::size_of,
     ops::{Deref, DerefMut},
-    time::Duration,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::sleep,
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Context, Result};
 use bytemuck::{cast_slice, Pod, Zeroable};
 use lasy::{Blanked, IsBlank, Lerp, Position, Weight};
-use log::{debug, error, info, log_enabled};
-use rusb::{DeviceHandle, Direction, GlobalContext, TransferType};
+use log::{debug, error, info, log_enabled, trace, warn};
+use pipeline::StreamState;
+pub use pipeline::{RenderConfig, StationaryWatchdog};
+use rusb::{
+    DeviceDescriptor, DeviceHandle, Direction, GlobalContext, Language, TransferType, UsbContext,
+};
+use stats::TransferStats;
 use thiserror::Error;
 pub mod animation;
+pub mod compose;
+pub mod csv;
+pub mod easing;
+pub mod geometry;
+pub mod ilda;
+pub mod interpolation;
+pub mod latency;
+pub mod output;
+pub mod palette;
+mod pipeline;
+pub mod profile;
+pub mod queue;
+mod safety;
+pub mod stats;
+#[cfg(feature = "image")]
+pub mod trace;
 
 pub const BYTES_PER_BATCH: usize = 64;
+/// Frame rate below which a scanned image visibly flickers.
+pub const FLICKER_FUSION_HZ: f32 = 24.0;
 #[derive(Copy, Clone, Pod, Zeroable, Ord, PartialOrd, Eq, PartialEq, Debug, Hash)]
 #[repr(C)]
 pub struct XY(pub u16);
 
+/// Bits per position axis. Everything XY-related derives from this, so hardware with a different
+/// position DAC only needs this changed.
+pub const XY_RESOLUTION: u32 = 12;
 pub const XY_MIN: u16 = 0;
-pub const XY_MAX: u16 = 4095;
+pub const XY_MAX: u16 = (1 << XY_RESOLUTION) - 1;
 
 impl XY {
     pub fn flip(self) -> Self {
@@ -30,18 +62,23 @@ impl XY {
 impl From<f32> for XY {
     fn from(f: f32) -> Self {
         let f = f.clamp(-1., 1.);
-        XY((XY_MAX as f32 * (f + 1.0) / 2.0) as u16)
+        XY((XY_MAX as f32 * (f + 1.0) / 2.0).round() as u16)
     }
 }
 
 impl From<f64> for XY {
     fn from(f: f64) -> Self {
         let f = f.clamp(-1., 1.);
-        XY((XY_MAX as f64 * (f + 1.0) / 2.0) as u16)
+        XY((XY_MAX as f64 * (f + 1.0) / 2.0).round() as u16)
     }
 }
 
-#[derive(Copy, Clone, Pod, Zeroable, Hash, Debug)]
+/// One DAC sample as it goes over the wire: four little-endian u16 words.
+///
+/// `rg` carries red in the low byte and green in the high byte, `b` carries blue in its low
+/// byte, followed by the 12-bit `x` and `y` positions. Color is therefore 8 bits per channel
+/// on the wire regardless of what `MaxDacValue` reports, which describes the XY range.
+#[derive(Copy, Clone, Pod, Zeroable, PartialEq, Eq, Hash, Debug)]
 #[repr(C)]
 pub struct LaserdockSample {
     rg: u16,
@@ -60,16 +97,22 @@ impl Lerp for LaserdockSample {
     type Scalar = f32;
 
     fn lerp(&self, dest: &Self, amt: Self::Scalar) -> Self {
+        // computed in f32 so that moving towards a smaller value doesn't underflow
         fn lerp_u16(a: u16, b: u16, amount: f32) -> u16 {
-            a + ((b as f32 - a as f32) * amount).round() as u16
+            (a as f32 + (b as f32 - a as f32) * amount).round() as u16
         }
 
-        LaserdockSample {
-            rg: lerp_u16(self.rg, dest.rg, amt),
-            b: lerp_u16(self.b, dest.b, amt),
-            x: XY(lerp_u16(self.x.0, dest.x.0, amt)),
-            y: XY(lerp_u16(self.y.0, dest.y.0, amt)),
-        }
+        // r and g share a word, interpolate them as separate bytes
+        let (r, g, b) = self.rgb();
+        let (dest_r, dest_g, dest_b) = dest.rgb();
+
+        LaserdockSample::new_xy(
+            lerp_u16(r as u16, dest_r as u16, amt) as u8,
+            lerp_u16(g as u16, dest_g as u16, amt) as u8,
+            lerp_u16(b as u16, dest_b as u16, amt) as u8,
+            XY(lerp_u16(self.x.0, dest.x.0, amt)),
+            XY(lerp_u16(self.y.0, dest.y.0, amt)),
+        )
     }
 }
 
@@ -82,10 +125,10 @@ impl Blanked for LaserdockSample {
     }
 }
 impl Position for LaserdockSample {
-    // [-1..-1]
+    // [-1..1]
     fn position(&self) -> [f32; 2] {
         fn to_f(xy: XY) -> f32 {
-            (2.0 * (xy.0 as f32) - XY_MAX as f32) / XY_MAX as f32 + 1.
+            (2.0 * (xy.0 as f32) - XY_MAX as f32) / XY_MAX as f32
         }
 
         [to_f(self.x), to_f(self.y)]
@@ -98,6 +141,18 @@ impl Weight for LaserdockSample {
     }
 }
 
+impl From<(u8, u8, u8, f64, f64)> for LaserdockSample {
+    fn from((r, g, b, x, y): (u8, u8, u8, f64, f64)) -> Self {
+        LaserdockSample::new(r, g, b, x, y)
+    }
+}
+
+impl From<(u8, u8, u8, f32, f32)> for LaserdockSample {
+    fn from((r, g, b, x, y): (u8, u8, u8, f32, f32)) -> Self {
+        LaserdockSample::new_xy(r, g, b, x.into(), y.into())
+    }
+}
+
 pub const SAMPLE_SIZE: usize = size_of::<LaserdockSample>() / size_of::<u8>();
 pub const SAMPLES_PER_BATCH: usize = BYTES_PER_BATCH / SAMPLE_SIZE;
 
@@ -106,6 +161,12 @@ impl LaserdockSample {
         Self::new_xy(r, g, b, x.into(), y.into())
     }
 
+    /// Screen-space coordinates: `u` and `v` in 0..1 with the origin top left and `v` growing
+    /// downwards, mapped onto the centered, Y-up scan field.
+    pub fn new_normalized(r: u8, g: u8, b: u8, u: f64, v: f64) -> LaserdockSample {
+        Self::new(r, g, b, u * 2. - 1., 1. - v * 2.)
+    }
+
     pub fn new_xy(r: u8, g: u8, b: u8, x: XY, y: XY) -> LaserdockSample {
         LaserdockSample {
             rg: r as u16 | (g as u16) << 8,
@@ -114,6 +175,109 @@ impl LaserdockSample {
             y,
         }
     }
+
+    /// Convenience for 16-bit color sources. The wire format carries 8 bits per channel, so each
+    /// channel is rounded to the nearest 8-bit value; this adds no color resolution.
+    pub fn from_u16_rgb(r16: u16, g16: u16, b16: u16, x: XY, y: XY) -> LaserdockSample {
+        fn to_u8(c: u16) -> u8 {
+            ((c as u32 + 128) / 257) as u8
+        }
+
+        Self::new_xy(to_u8(r16), to_u8(g16), to_u8(b16), x, y)
+    }
+
+    /// The sample as the firmware expects it: `rg`, `b`, `x`, `y`, each a little-endian u16.
+    pub fn to_wire_bytes(&self) -> [u8; SAMPLE_SIZE] {
+        let mut bytes = [0; SAMPLE_SIZE];
+        for (chunk, word) in bytes
+            .chunks_exact_mut(2)
+            .zip([self.rg, self.b, self.x.0, self.y.0])
+        {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Inverse of `to_wire_bytes`, e.g. to check what was sent. The firmware can't read samples
+    /// back from its ring buffer, so this is as close as verification gets.
+    pub fn from_wire_bytes(bytes: [u8; SAMPLE_SIZE]) -> LaserdockSample {
+        let word = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        LaserdockSample {
+            rg: word(0),
+            b: word(2),
+            x: XY(word(4)),
+            y: XY(word(6)),
+        }
+    }
+
+    /// Same color, moved to `x`, `y` in normalized coordinates.
+    pub fn with_position(&self, x: f32, y: f32) -> LaserdockSample {
+        LaserdockSample {
+            x: x.into(),
+            y: y.into(),
+            ..*self
+        }
+    }
+
+    /// Whether both samples address exactly the same DAC position, ignoring color.
+    pub fn same_position(&self, other: &Self) -> bool {
+        self.x == other.x && self.y == other.y
+    }
+
+    /// Euclidean distance to `other` in normalized coordinates.
+    pub fn distance_to(&self, other: &Self) -> f32 {
+        geometry::distance(self, other)
+    }
+
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        (self.rg as u8, (self.rg >> 8) as u8, self.b as u8)
+    }
+
+    pub fn with_rgb(&self, r: u8, g: u8, b: u8) -> LaserdockSample {
+        Self::new_xy(r, g, b, self.x, self.y)
+    }
+}
+
+/// Wire bytes for a run of samples. On little-endian hosts the in-memory layout already is the
+/// wire layout (`repr(C)`, no padding, which `Pod` guarantees), so this borrows.
+fn wire_bytes(samples: &[LaserdockSample]) -> Cow<'_, [u8]> {
+    if cfg!(target_endian = "little") {
+        Cow::Borrowed(cast_slice(samples))
+    } else {
+        Cow::Owned(samples.iter().flat_map(|s| s.to_wire_bytes()).collect())
+    }
+}
+
+/// Layout of samples on the data endpoint. Only the Laserdock layout is known so far; firmware
+/// with a different layout gets its own variant here.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SampleFormat {
+    /// `rg`, `b`, `x`, `y` as little-endian u16s, see `LaserdockSample`.
+    #[default]
+    Laserdock,
+}
+
+impl SampleFormat {
+    pub fn sample_size(self) -> usize {
+        match self {
+            SampleFormat::Laserdock => SAMPLE_SIZE,
+        }
+    }
+
+    pub fn encode(self, samples: &[LaserdockSample]) -> Cow<'_, [u8]> {
+        match self {
+            SampleFormat::Laserdock => wire_bytes(samples),
+        }
+    }
+}
+
+fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
 enum SetCommand {
@@ -122,7 +286,9 @@ enum SetCommand {
     DacRate = 0x82,
 }
 
-enum GetCommand {
+/// Control commands that read a device register.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GetCommand {
     OutputEnabled = 0x81,
     DacRate = 0x83,
     MaxDacRate = 0x84,
@@ -166,6 +332,12 @@ impl From<Buf> for u8 {
     }
 }
 
+impl From<Buf> for [u8; RECV_BUF_LEN] {
+    fn from(buf: Buf) -> Self {
+        buf.0[..RECV_BUF_LEN].try_into().unwrap()
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum BusError {
     #[error("incomplete write: {0} of {1} bytes")]
@@ -176,24 +348,191 @@ pub enum BusError {
 
     #[error("Unexpected content: {0} instead of {1}")]
     UnexpectedContent(u8, u8),
+
+    #[error("partial sample: {0} bytes is not a multiple of {1}")]
+    PartialSample(usize, usize),
+
+    #[error("response echoes command {0:#04x} instead of {1:#04x}")]
+    CommandMismatch(u8, u8),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TransportKind {
+    Usb,
+    Network,
+}
+
+/// How a device is connected, for adapting buffering strategy to the transport.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TransportInfo {
+    pub kind: TransportKind,
+    /// Largest payload sent in a single packet.
+    pub max_transfer_size: usize,
+    pub typical_latency: Duration,
+}
+
+/// Snapshot of the device's readable registers, see `LaserCube::read_all_registers`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Registers {
+    pub output_enabled: bool,
+    pub dac_rate: u32,
+    pub max_dac_rate: u32,
+    pub min_dac_rate: u32,
+    pub max_dac_value: u32,
+    pub version_major: u32,
+    pub version_minor: u32,
+}
+
+/// Device telemetry. `None` means the firmware doesn't report that value.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Health {
+    pub temperature_celsius: Option<f32>,
+    pub fault_flags: Option<u32>,
+    pub interlock_closed: Option<bool>,
+}
+
+/// What `set_dac_rate` does after changing the rate. Changing it while streaming can cause a
+/// visible hiccup while the firmware applies the new rate; clearing the queued samples and/or
+/// pausing briefly makes the switch clean at the cost of some latency. The default does neither.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DacRateSettle {
+    pub clear_ringbuffer: bool,
+    pub delay: Duration,
 }
 
-pub struct LaserCube {
-    device: DeviceHandle<GlobalContext>,
+#[derive(Error, Debug)]
+pub enum DeviceError {
+    #[error("{0} is not reported by this firmware")]
+    Unsupported(&'static str),
+
+    #[error("interlock open, refusing to enable output")]
+    InterlockOpen,
+}
+
+#[derive(Error, Debug)]
+pub enum OpenError {
+    #[error("LaserCube is busy: another process (maybe another instance of this program) is probably using it")]
+    DeviceBusy,
+}
+
+pub struct LaserCube<T: UsbContext = GlobalContext> {
+    context: T,
+    device: Arc<DeviceHandle<T>>,
     control_read: u8,
     control_write: u8,
     data_write: u8,
+    sample_format: SampleFormat,
+    render: RenderConfig,
+    dac_rate: Option<u32>,
+    dac_rate_settle: DacRateSettle,
+    clamped_samples: AtomicUsize,
+    output_ramp: Option<(Instant, Duration)>,
+    park_on_disable: bool,
+    skip_duplicate_frames: bool,
+    blank_stream_edges: bool,
+    stream_started: AtomicBool,
+    queued_frame: Mutex<Option<(u64, Instant)>>,
+    stats: Option<Mutex<TransferStats>>,
+    last_send: Mutex<Option<Instant>>,
+    low_latency: bool,
+    strict: bool,
+    dump_traffic: bool,
+    heartbeat_interval: Option<Duration>,
+    stream: StreamState,
+    buffer_capacity: Mutex<Option<usize>>,
 }
 
+const RECV_BUF_LEN: usize = 64;
+
 impl LaserCube {
+    pub fn open_first() -> Result<LaserCube> {
+        Self::open_with_context(&GlobalContext::default())
+    }
+}
+
+impl<T: UsbContext> LaserCube<T> {
     const USB_VENDOR_ID: u16 = 0x1fc9;
     const USB_PRODUCT_ID: u16 = 0x04d8;
     const CONTROL_INTERFACE: u8 = 0;
     const DATA_INTERFACE: u8 = 1;
-    const RECV_BUF_LEN: usize = 64;
+    const LOW_LATENCY_TIMEOUT: Duration = Duration::from_millis(50);
+    const PROBE_TIMEOUT: Duration = Duration::from_millis(100);
+    const MAX_PROBED_CAPACITY: usize = 1 << 20;
+
+    /// Open the first LaserCube found through a caller-managed libusb context, for applications
+    /// that don't use the global one.
+    pub fn open_with_context(context: &T) -> Result<Self>
+    where
+        T: 'static,
+    {
+        let (device, control_read, control_write, data_write) = Self::connect(context)?;
+        let device = Arc::new(device);
+        safety::register(&device, control_read, control_write, data_write);
 
-    pub fn open_first() -> Result<LaserCube> {
-        let device = rusb::devices()?
+        let mut laser_cube = LaserCube {
+            context: context.clone(),
+            device,
+            control_read,
+            control_write,
+            data_write,
+            sample_format: SampleFormat::default(),
+            render: RenderConfig::default(),
+            dac_rate: None,
+            dac_rate_settle: DacRateSettle::default(),
+            clamped_samples: AtomicUsize::new(0),
+            output_ramp: None,
+            park_on_disable: false,
+            skip_duplicate_frames: false,
+            blank_stream_edges: true,
+            stream_started: AtomicBool::new(false),
+            queued_frame: Mutex::new(None),
+            stats: None,
+            last_send: Mutex::new(None),
+            low_latency: false,
+            strict: false,
+            dump_traffic: false,
+            heartbeat_interval: None,
+            stream: StreamState::default(),
+            buffer_capacity: Mutex::new(None),
+        };
+
+        laser_cube.start()?;
+
+        Ok(laser_cube)
+    }
+
+    /// Re-open the device after a disconnect and restore the logical configuration (DAC rate
+    /// and send pipeline settings) so the render loop can carry on as before.
+    pub fn reconnect(&mut self) -> Result<()>
+    where
+        T: 'static,
+    {
+        info!("reconnecting");
+        safety::release(
+            &mut self.device,
+            &[Self::CONTROL_INTERFACE, Self::DATA_INTERFACE],
+        );
+
+        let (device, control_read, control_write, data_write) = Self::connect(&self.context)?;
+        self.device = Arc::new(device);
+        safety::register(&self.device, control_read, control_write, data_write);
+        self.control_read = control_read;
+        self.control_write = control_write;
+        self.data_write = data_write;
+        self.stream.reset(self.render.blank_delay_samples);
+
+        self.start()?;
+
+        if let Some(rate) = self.dac_rate {
+            self.write_u32(SetCommand::DacRate, rate)?;
+        }
+
+        Ok(())
+    }
+
+    fn connect(context: &T) -> Result<(DeviceHandle<T>, u8, u8, u8)> {
+        let device = context
+            .devices()?
             .iter()
             .filter_map(|device| {
                 let descriptor = device.device_descriptor().ok()?;
@@ -216,8 +555,12 @@ impl LaserCube {
 
         let mut device = device.open()?;
 
-        device.claim_interface(Self::CONTROL_INTERFACE)?;
-        device.claim_interface(Self::DATA_INTERFACE)?;
+        for interface in [Self::CONTROL_INTERFACE, Self::DATA_INTERFACE] {
+            match device.claim_interface(interface) {
+                Err(rusb::Error::Busy) => return Err(OpenError::DeviceBusy.into()),
+                result => result?,
+            }
+        }
 
         for interface in config_desc.interfaces() {
             for interface_desc in interface.descriptors() {
@@ -250,34 +593,45 @@ impl LaserCube {
         let control_write = control_write.ok_or(anyhow!("control interface not found"))?;
         let data_write = data_write.ok_or(anyhow!("data interface not found"))?;
 
-        let mut laser_cube = LaserCube {
-            device: device,
-            control_read,
-            control_write,
-            data_write,
-        };
+        Ok((device, control_read, control_write, data_write))
+    }
 
+    fn start(&mut self) -> Result<()> {
         if log_enabled!(log::Level::Debug) {
-            laser_cube.diagnostics()?
+            self.diagnostics()?
         }
 
-        laser_cube.clear_ringbuffer()?;
-        laser_cube.enable_output()?;
-        if !laser_cube.output_enabled()? {
+        let max_dac_value = self.max_dac_value()?;
+        if max_dac_value != XY_MAX as u32 {
+            warn!(
+                "device reports a position range of 0..={}, but samples are encoded for 0..={}",
+                max_dac_value, XY_MAX
+            );
+        }
+
+        self.clear_ringbuffer()?;
+        self.enable_output()?;
+        if !self.output_enabled()? {
             return Err(anyhow!("failed to enable output"));
         } else {
             info!("Output enabled!")
         }
 
-        Ok(laser_cube)
+        Ok(())
     }
 
-    fn read<T: From<Buf>>(&self, command: GetCommand) -> Result<T> {
+    fn read<R: From<Buf>>(&self, command: GetCommand) -> Result<R> {
         let recv = self.write_buf(&[command as u8])?;
 
         Ok(recv.into())
     }
 
+    /// The complete response to `command`, for replies carrying more than one value. Byte 1 is
+    /// the status, already checked to be zero; values start at byte 2.
+    pub fn read_raw(&self, command: GetCommand) -> Result<[u8; RECV_BUF_LEN]> {
+        self.read(command)
+    }
+
     fn write_u32(&mut self, command: SetCommand, value: u32) -> Result<()> {
         let mut buf = Vec::with_capacity(5);
 
@@ -294,10 +648,13 @@ impl LaserCube {
 
     fn write_buf(&self, buf: &[u8]) -> Result<Buf> {
         let timeout = Duration::from_secs(1);
+        if self.dump_traffic {
+            debug!("control out: {}", hex_dump(buf));
+        }
 
         let written = self
             .device
-            .write_bulk(self.control_write, &buf, timeout)
+            .write_bulk(self.control_write, buf, timeout)
             .context("write_bulk")?;
 
         if written != buf.len() {
@@ -310,25 +667,236 @@ impl LaserCube {
             .read_bulk(self.control_read, &mut recv, timeout)
             .context("read_bulk")?;
 
-        if read != LaserCube::RECV_BUF_LEN {
-            return Err(BusError::IncompleteResponse(read, LaserCube::RECV_BUF_LEN).into());
+        if self.dump_traffic {
+            debug!("control in: {}", hex_dump(&recv[..read]));
+        }
+
+        if read != RECV_BUF_LEN {
+            return Err(BusError::IncompleteResponse(read, RECV_BUF_LEN).into());
         }
 
         if recv[1] != 0 {
             return Err(BusError::UnexpectedContent(recv[1], 0).into());
         }
 
+        if self.strict && recv[0] != buf[0] {
+            return Err(BusError::CommandMismatch(recv[0], buf[0]).into());
+        }
+
         Ok(recv)
     }
 
+    /// Append samples to the device's ring buffer.
+    ///
+    /// The ring buffer is a FIFO: the data endpoint has no notion of an offset, and the only
+    /// buffer command known to work is `clear_ringbuffer` (libLaserdock also names a sample
+    /// count register, 0x89, but what it reports is unverified). Samples are scanned once in the
+    /// order they arrive, so there is no way to patch part of an already queued frame; an
+    /// incremental update means sending the full frame again. An empty slice is a no-op.
     pub fn send_samples(&self, buf: &[LaserdockSample]) -> Result<()> {
-        self.send(cast_slice(buf))
+        self.send_samples_counted(buf)?;
+        Ok(())
+    }
+
+    /// `send_samples`, returning how many samples the frame takes up on the wire, lead-in
+    /// included. A skipped duplicate counts too, since its earlier copy is still queued.
+    fn send_samples_counted(&self, buf: &[LaserdockSample]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let lead_in = self.stream_lead_in(buf);
+        if !self.processing_active() && lead_in.is_empty() {
+            self.send_frame(buf)?;
+            return Ok(buf.len());
+        }
+
+        let mut frame = Vec::with_capacity(lead_in.len() + buf.len());
+        frame.extend(lead_in);
+        frame.extend_from_slice(buf);
+        self.process(&mut frame);
+        self.send_frame(&frame)?;
+        Ok(frame.len())
+    }
+
+    fn send_frame(&self, frame: &[LaserdockSample]) -> Result<()> {
+        let bytes = self.sample_format.encode(frame);
+        if self.is_duplicate_frame(&bytes, frame.len()) {
+            return Ok(());
+        }
+        self.send(&bytes)
+    }
+
+    /// Hard cut: drop everything still queued, then send `points`. Unlike `send_samples`, which
+    /// appends, nothing of the previous scene is drawn after this returns.
+    pub fn cut_to(&mut self, points: &[LaserdockSample]) -> Result<()> {
+        self.clear_ringbuffer()?;
+        self.send_samples(points)
+    }
+
+    /// Send a frame once and return when it has been scanned.
+    ///
+    /// The wait is computed from the samples sent, stream lead-in included, and the device's DAC
+    /// rate, so it assumes the ring buffer was empty beforehand; call `clear_ringbuffer` first to
+    /// make sure.
+    pub fn draw_once(&self, points: &[LaserdockSample]) -> Result<()> {
+        let rate = self.dac_rate()?.max(1);
+        let sent = self.send_samples_counted(points)?;
+        sleep(Duration::from_secs_f64(sent as f64 / rate as f64));
+        Ok(())
+    }
+
+    /// How many times per second a frame of `points` samples is redrawn at the current DAC rate.
+    pub fn refresh_rate(&self, points: usize) -> Result<f32> {
+        Ok(self.dac_rate()? as f32 / points.max(1) as f32)
+    }
+
+    /// Whether a frame of `points` samples refreshes below `FLICKER_FUSION_HZ`; see
+    /// `refresh_rate` for the actual rate.
+    pub fn will_flicker(&self, points: usize) -> Result<bool> {
+        Ok(self.refresh_rate(points)? < FLICKER_FUSION_HZ)
+    }
+
+    /// Smoke test: stream a slowly rotating Lissajous figure for a few seconds. The previous DAC
+    /// rate is restored afterwards.
+    pub fn demo(&mut self) -> Result<()> {
+        const DEMO_DAC_RATE: u32 = 30_000;
+        const DEMO_DURATION: Duration = Duration::from_secs(5);
+
+        let previous_rate = self.dac_rate()?;
+        self.set_dac_rate(DEMO_DAC_RATE)?;
+
+        let start = Instant::now();
+        let result = (|| {
+            while start.elapsed() < DEMO_DURATION {
+                let delta = start.elapsed().as_secs_f64();
+                let figure = geometry::lissajous(3., 2., delta, 600, (0, 255, 0));
+                self.draw_once(&figure)?;
+            }
+            Ok(())
+        })();
+
+        // restore even if drawing failed, then report the first error
+        let restored = self.set_dac_rate(previous_rate);
+        result.and(restored)
+    }
+
+    /// Send frames from `next_frame` forever, reconnecting after errors. Only returns, with the
+    /// last error, once `max_failures` sends and reconnects have failed without a successful send
+    /// in between.
+    pub fn run_loop(
+        &mut self,
+        max_failures: usize,
+        mut next_frame: impl FnMut() -> Vec<LaserdockSample>,
+    ) -> anyhow::Error
+    where
+        T: 'static,
+    {
+        let mut failures = 0;
+        loop {
+            let mut result = self.send_samples(&next_frame());
+            if result.is_ok() {
+                failures = 0;
+                continue;
+            }
+
+            while let Err(e) = result {
+                failures += 1;
+                warn!(
+                    "run loop: failure {} of {}: {:#}",
+                    failures, max_failures, e
+                );
+                if failures >= max_failures {
+                    return e;
+                }
+
+                sleep(Duration::from_millis(100) * failures as u32);
+                result = self.reconnect();
+            }
+            info!("run loop: reconnected");
+        }
     }
 
+    /// Stream lazily generated samples, one batch at a time, without collecting them first.
+    pub fn stream_iter(&self, iter: impl Iterator<Item = LaserdockSample>) -> Result<()> {
+        let mut batch = [LaserdockSample::zeroed(); SAMPLES_PER_BATCH];
+        let mut len = 0;
+        let mut last = None;
+
+        for sample in iter {
+            last = Some(sample);
+            batch[len] = sample;
+            len += 1;
+            if len == SAMPLES_PER_BATCH {
+                self.send_samples(&batch)?;
+                len = 0;
+            }
+        }
+
+        if len > 0 {
+            self.send_samples(&batch[..len])?;
+        }
+
+        if let Some(last) = last.filter(|_| self.blank_stream_edges) {
+            self.send_samples(&[last.blanked()])?;
+        }
+
+        Ok(())
+    }
+
+    /// Split `points` into slices of at most `SAMPLES_PER_BATCH` samples, one USB batch each.
+    pub fn batch_iter<'a>(
+        &self,
+        points: &'a [LaserdockSample],
+    ) -> impl Iterator<Item = &'a [LaserdockSample]> {
+        points.chunks(SAMPLES_PER_BATCH)
+    }
+
+    /// Raw sample bytes. The length must be a multiple of the sample format's size, a trailing
+    /// partial sample would be misinterpreted by the device.
     pub fn send(&self, buf: &[u8]) -> Result<()> {
-        let timeout = Duration::from_secs(1);
+        let sample_size = self.sample_format.sample_size();
+        if !buf.len().is_multiple_of(sample_size) {
+            return Err(BusError::PartialSample(buf.len(), sample_size).into());
+        }
 
-        let written = self.device.write_bulk(self.data_write, &buf, timeout)?;
+        if self.low_latency {
+            for chunk in buf.chunks(BYTES_PER_BATCH) {
+                self.write_data(chunk, Self::LOW_LATENCY_TIMEOUT)?;
+            }
+            return Ok(());
+        }
+
+        self.write_data(buf, Duration::from_secs(1))
+    }
+
+    /// Like `send`, but a short write isn't fatal: the unwritten tail is sent again until the
+    /// whole buffer is through or `timeout` has elapsed. For hosts that accept partial bulk
+    /// transfers under load.
+    pub fn send_all(&self, buf: &[u8], timeout: Duration) -> Result<()> {
+        let sample_size = self.sample_format.sample_size();
+        if !buf.len().is_multiple_of(sample_size) {
+            return Err(BusError::PartialSample(buf.len(), sample_size).into());
+        }
+
+        let deadline = Instant::now() + timeout;
+        let mut sent = 0;
+        while sent < buf.len() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(BusError::IncompleteWrite(sent, buf.len()).into());
+            }
+            let written = self.write_bulk_data(&buf[sent..], remaining)?;
+            if written < buf.len() - sent {
+                debug!("short write, {} of {} bytes", written, buf.len() - sent);
+            }
+            sent += written;
+        }
+
+        Ok(())
+    }
+
+    fn write_data(&self, buf: &[u8], timeout: Duration) -> Result<()> {
+        let written = self.write_bulk_data(buf, timeout)?;
 
         if written != buf.len() {
             return Err(BusError::IncompleteWrite(written, buf.len()).into());
@@ -337,6 +905,125 @@ impl LaserCube {
         Ok(())
     }
 
+    fn write_bulk_data(&self, buf: &[u8], timeout: Duration) -> Result<usize> {
+        if self.dump_traffic && log_enabled!(log::Level::Trace) {
+            trace!("data out: {}", hex_dump(buf));
+        }
+        let written = match &self.stats {
+            Some(stats) => {
+                let start = Instant::now();
+                let written = self.device.write_bulk(self.data_write, buf, timeout)?;
+                stats
+                    .lock()
+                    .unwrap()
+                    .record(buf.len(), written, start.elapsed());
+                written
+            }
+            None => self.device.write_bulk(self.data_write, buf, timeout)?,
+        };
+        *self.last_send.lock().unwrap() = Some(Instant::now());
+
+        Ok(written)
+    }
+
+    /// Log the bytes of every control command and response at debug level, and of every data
+    /// transfer at trace level, for protocol analysis. Off by default.
+    pub fn set_dump_traffic(&mut self, dump: bool) {
+        self.dump_traffic = dump;
+    }
+
+    pub fn dump_traffic(&self) -> bool {
+        self.dump_traffic
+    }
+
+    /// Wire layout used by `send_samples`. Defaults to `SampleFormat::Laserdock`, the only layout
+    /// current firmware speaks; the device can't be queried for it.
+    pub fn set_sample_format(&mut self, format: SampleFormat) {
+        self.sample_format = format;
+    }
+
+    pub fn sample_format(&self) -> SampleFormat {
+        self.sample_format
+    }
+
+    /// Also check that every control response echoes the command it answers, to catch firmware
+    /// that deviates from the expected format. Off by default.
+    ///
+    /// The bytes after the value are not checked: their contents are undocumented, and some
+    /// replies carry more than one value there (see `read_raw`).
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// For interactive use (e.g. a laser pen following the mouse): every batch goes out as its
+    /// own transfer with a short timeout, so each update reaches the device as soon as possible.
+    /// Costs more CPU and USB overhead per sample than the default single large transfer.
+    pub fn set_low_latency(&mut self, low_latency: bool) {
+        self.low_latency = low_latency;
+    }
+
+    pub fn low_latency(&self) -> bool {
+        self.low_latency
+    }
+
+    /// Whether anything was written to the data endpoint within the last `idle_after`, e.g. for a
+    /// "streaming" indicator. Heartbeat batches count as activity.
+    pub fn is_streaming(&self, idle_after: Duration) -> bool {
+        self.last_send
+            .lock()
+            .unwrap()
+            .is_some_and(|last| last.elapsed() < idle_after)
+    }
+
+    /// Opt in to keep-alive batches: once nothing has been sent for `interval`, `heartbeat`
+    /// sends a blanked batch so the buffer never drains with the beam parked lit.
+    pub fn set_heartbeat(&mut self, interval: Option<Duration>) {
+        self.heartbeat_interval = interval;
+    }
+
+    /// Call regularly, e.g. from the render loop or a timer. Returns whether a keep-alive batch
+    /// was sent.
+    pub fn heartbeat(&self) -> Result<bool> {
+        let interval = match self.heartbeat_interval {
+            Some(interval) => interval,
+            None => return Ok(false),
+        };
+
+        let idle = self
+            .last_send
+            .lock()
+            .unwrap()
+            .is_none_or(|last| last.elapsed() >= interval);
+        if idle {
+            let blank = [LaserdockSample::new(0, 0, 0, 0., 0.); SAMPLES_PER_BATCH];
+            self.send(&self.sample_format.encode(&blank))?;
+        }
+
+        Ok(idle)
+    }
+
+    /// Start or stop collecting `TransferStats` for the data endpoint. Disabled by default, in
+    /// which case `send` does no bookkeeping at all.
+    pub fn enable_stats(&mut self, enabled: bool) {
+        self.stats = if enabled {
+            Some(Mutex::new(TransferStats::default()))
+        } else {
+            None
+        };
+    }
+
+    /// The statistics collected since the last call, resetting them.
+    pub fn take_stats(&self) -> TransferStats {
+        self.stats
+            .as_ref()
+            .map(|stats| std::mem::take(&mut *stats.lock().unwrap()))
+            .unwrap_or_default()
+    }
+
     pub fn max_dac_rate(&self) -> Result<u32> {
         self.read::<u32>(GetCommand::MaxDacRate)
     }
@@ -345,6 +1032,17 @@ impl LaserCube {
         self.read::<u32>(GetCommand::MinDacRate)
     }
 
+    pub fn max_dac_value(&self) -> Result<u32> {
+        self.read::<u32>(GetCommand::MaxDacValue)
+    }
+
+    /// Round-trip time of one cheap control command (a DAC rate read).
+    pub fn ping(&self) -> Result<Duration> {
+        let start = Instant::now();
+        self.dac_rate()?;
+        Ok(start.elapsed())
+    }
+
     pub fn dac_rate(&self) -> Result<u32> {
         self.read::<u32>(GetCommand::DacRate)
     }
@@ -352,30 +1050,211 @@ impl LaserCube {
     pub fn set_dac_rate(&mut self, rate: u32) -> Result<()> {
         let min = self.min_dac_rate()?;
         let max = self.max_dac_rate()?;
-        self.write_u32(SetCommand::DacRate, rate.clamp(min, max))
+        let rate = rate.clamp(min, max);
+        self.write_u32(SetCommand::DacRate, rate)?;
+        self.dac_rate = Some(rate);
+
+        if self.dac_rate_settle.clear_ringbuffer {
+            self.clear_ringbuffer()?;
+        }
+        if !self.dac_rate_settle.delay.is_zero() {
+            sleep(self.dac_rate_settle.delay);
+        }
+        Ok(())
+    }
+
+    pub fn set_dac_rate_settle(&mut self, settle: DacRateSettle) {
+        self.dac_rate_settle = settle;
+    }
+
+    pub fn dac_rate_settle(&self) -> DacRateSettle {
+        self.dac_rate_settle
     }
 
+    /// Drop everything queued on the device. The blank delay, persistence and watchdog state of
+    /// the dropped samples goes with it, so none of it leaks into the next send.
     pub fn clear_ringbuffer(&mut self) -> Result<()> {
+        self.reset_queue()
+    }
+
+    fn reset_queue(&self) -> Result<()> {
         debug!("clearing ring buffer");
-        self.write_u8(SetCommand::ClearRingBuffer, 0)
+        self.force_resend();
+        self.stream_started.store(false, Ordering::Relaxed);
+        self.stream.reset(self.render.blank_delay_samples);
+        self.write_buf(&[SetCommand::ClearRingBuffer as u8, 0])?;
+        Ok(())
+    }
+
+    /// Ring buffer size in samples, probed on first use and cached: blanked batches are written
+    /// into the cleared buffer until a write times out. The result includes whatever the USB
+    /// stack buffers on the way. Call it with output disabled, otherwise the scanner drains the
+    /// buffer while probing. Clears the ring buffer before and after.
+    pub fn buffer_capacity(&self) -> Result<usize> {
+        if let Some(capacity) = *self.buffer_capacity.lock().unwrap() {
+            return Ok(capacity);
+        }
+
+        self.reset_queue()?;
+        let blank = [LaserdockSample::new(0, 0, 0, 0., 0.); SAMPLES_PER_BATCH];
+        let bytes = self.sample_format.encode(&blank);
+        let mut capacity = 0;
+        while capacity < Self::MAX_PROBED_CAPACITY {
+            match self
+                .device
+                .write_bulk(self.data_write, &bytes, Self::PROBE_TIMEOUT)
+            {
+                Ok(written) => capacity += written / self.sample_format.sample_size(),
+                Err(rusb::Error::Timeout) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.reset_queue()?;
+
+        if capacity >= Self::MAX_PROBED_CAPACITY {
+            return Err(anyhow!(
+                "no write blocked after {} samples, is output enabled?",
+                capacity
+            ));
+        }
+        debug!("ring buffer holds {} samples", capacity);
+        *self.buffer_capacity.lock().unwrap() = Some(capacity);
+        Ok(capacity)
     }
 
     pub fn enable_output(&mut self) -> Result<()> {
+        if self.health()?.interlock_closed == Some(false) {
+            return Err(DeviceError::InterlockOpen.into());
+        }
+
         debug!("enabling output");
         self.write_u8(SetCommand::EnableOutput, 1)?;
         Ok(())
     }
 
+    /// Enable output and fade brightness in from zero over `duration`, avoiding a full-power
+    /// flash on turn-on. The ring buffer is cleared first since already queued samples can't be
+    /// dimmed; the ramp applies to everything sent afterwards.
+    pub fn enable_output_ramped(&mut self, duration: Duration) -> Result<()> {
+        self.clear_ringbuffer()?;
+        self.enable_output()?;
+        self.output_ramp = Some((Instant::now(), duration));
+        Ok(())
+    }
+
     pub fn disable_output(&mut self) -> Result<()> {
+        if self.park_on_disable {
+            self.park()?;
+        }
+
         debug!("disabling output");
         self.write_u8(SetCommand::EnableOutput, 0)?;
         Ok(())
     }
 
+    /// Center the galvos with a blanked beam, then disable output.
+    pub fn disable_output_parked(&mut self) -> Result<()> {
+        self.park()?;
+        self.write_u8(SetCommand::EnableOutput, 0)?;
+        Ok(())
+    }
+
+    /// Make every `disable_output` park the galvos at the center first. Off by default.
+    pub fn set_park_on_disable(&mut self, park: bool) {
+        self.park_on_disable = park;
+    }
+
+    pub fn park_on_disable(&self) -> bool {
+        self.park_on_disable
+    }
+
+    fn park(&mut self) -> Result<()> {
+        debug!("parking galvos at center");
+        self.clear_ringbuffer()?;
+        self.draw_once(&[LaserdockSample::new(0, 0, 0, 0., 0.); SAMPLES_PER_BATCH])
+    }
+
+    /// Enable output until the returned guard goes out of scope, including by unwinding.
+    pub fn output_guard(&mut self) -> Result<OutputGuard<'_, T>> {
+        self.enable_output()?;
+        Ok(OutputGuard { laser_cube: self })
+    }
+
     pub fn output_enabled(&self) -> Result<bool> {
         Ok(true)
     }
 
+    /// Issue every known `GetCommand` and collect the results.
+    pub fn read_all_registers(&self) -> Result<Registers> {
+        Ok(Registers {
+            output_enabled: self.read::<u8>(GetCommand::OutputEnabled)? != 0,
+            dac_rate: self.dac_rate()?,
+            max_dac_rate: self.max_dac_rate()?,
+            min_dac_rate: self.min_dac_rate()?,
+            max_dac_value: self.max_dac_value()?,
+            version_major: self.read::<u32>(GetCommand::VersionMajor)?,
+            version_minor: self.read::<u32>(GetCommand::VersionMinor)?,
+        })
+    }
+
+    /// Only USB is implemented so far; `TransportKind::Network` is reserved for the WiFi
+    /// LaserCube.
+    pub fn kind(&self) -> TransportInfo {
+        TransportInfo {
+            kind: TransportKind::Usb,
+            max_transfer_size: BYTES_PER_BATCH,
+            typical_latency: Duration::from_millis(1),
+        }
+    }
+
+    pub fn serial_number(&self) -> Result<String> {
+        self.read_string(DeviceHandle::read_serial_number_string)
+    }
+
+    pub fn product_string(&self) -> Result<String> {
+        self.read_string(DeviceHandle::read_product_string)
+    }
+
+    pub fn manufacturer_string(&self) -> Result<String> {
+        self.read_string(DeviceHandle::read_manufacturer_string)
+    }
+
+    // Read a descriptor string in the first language the device reports.
+    fn read_string(
+        &self,
+        read: impl FnOnce(
+            &DeviceHandle<T>,
+            Language,
+            &DeviceDescriptor,
+            Duration,
+        ) -> rusb::Result<String>,
+    ) -> Result<String> {
+        let timeout = Duration::from_secs(1);
+        let descriptor = self.device.device().device_descriptor()?;
+        let language = *self
+            .device
+            .read_languages(timeout)?
+            .first()
+            .ok_or(anyhow!("device reports no string languages"))?;
+
+        Ok(read(&self.device, language, &descriptor, timeout)?)
+    }
+
+    /// Whether the hardware interlock is closed. Returns `DeviceError::Unsupported` while the
+    /// firmware doesn't report interlock state (see `health`); `enable_output` only refuses to
+    /// enable when the interlock is known to be open.
+    pub fn interlock_ok(&self) -> Result<bool> {
+        self.health()?
+            .interlock_closed
+            .ok_or_else(|| DeviceError::Unsupported("interlock state").into())
+    }
+
+    /// No known firmware command reports temperature, faults or interlock state yet, so every
+    /// field is `None` for now. Once a register is found it gets a `GetCommand` and is read here.
+    pub fn health(&self) -> Result<Health> {
+        Ok(Health::default())
+    }
+
     pub fn diagnostics(&self) -> Result<()> {
         let timeout = Duration::from_secs(1);
         let device_handle = &self.device;
@@ -389,25 +1268,25 @@ impl LaserCube {
         );
         debug!("Languages: {:?}", languages);
 
-        if languages.len() > 0 {
+        if !languages.is_empty() {
             let language = languages[0];
 
             debug!(
                 "Manufacturer: {:?}",
                 device_handle
-                    .read_manufacturer_string(language, &descriptor, timeout)
+                    .read_manufacturer_string(language, descriptor, timeout)
                     .unwrap_or("?".to_string())
             );
             debug!(
                 "Product: {:?}",
                 device_handle
-                    .read_product_string(language, &descriptor, timeout)
+                    .read_product_string(language, descriptor, timeout)
                     .unwrap_or("?".to_string())
             );
             debug!(
                 "Serial Number: {:?}",
                 device_handle
-                    .read_serial_number_string(language, &descriptor, timeout)
+                    .read_serial_number_string(language, descriptor, timeout)
                     .unwrap_or("?".to_string())
             );
         }
@@ -421,10 +1300,7 @@ impl LaserCube {
         debug!("min dac rate {}", self.min_dac_rate()?);
         debug!("max dac rate {}", self.max_dac_rate()?);
         debug!("dac rate {}", self.dac_rate()?);
-        debug!(
-            "max dac value {}",
-            self.read::<u32>(GetCommand::MaxDacValue)?
-        );
+        debug!("max dac value {}", self.max_dac_value()?);
 
         Ok(())
     }
@@ -435,3 +1311,39 @@ impl Default for LaserCube {
         Self::open_first().unwrap()
     }
 }
+
+pub struct OutputGuard<'a, T: UsbContext = GlobalContext> {
+    laser_cube: &'a mut LaserCube<T>,
+}
+
+impl<T: UsbContext> Deref for OutputGuard<'_, T> {
+    type Target = LaserCube<T>;
+
+    fn deref(&self) -> &Self::Target {
+        self.laser_cube
+    }
+}
+
+impl<T: UsbContext> DerefMut for OutputGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.laser_cube
+    }
+}
+
+impl<T: UsbContext> Drop for OutputGuard<'_, T> {
+    fn drop(&mut self) {
+        // Bypass the pipeline: the blank delay line would otherwise light the first samples of
+        // this batch with the previous frame's colors.
+        let laser_cube = &mut *self.laser_cube;
+        laser_cube
+            .stream
+            .reset(laser_cube.render.blank_delay_samples);
+        let blank = [LaserdockSample::new(0, 0, 0, 0., 0.); SAMPLES_PER_BATCH];
+        if let Err(e) = laser_cube.send(&laser_cube.sample_format.encode(&blank)) {
+            error!("output guard: failed to send blank frame: {:?}", e);
+        }
+        if let Err(e) = laser_cube.disable_output() {
+            error!("output guard: failed to disable output: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use rusb::UsbContext;
+use thiserror::Error;
+
+use crate::{csv, LaserCube, LaserdockSample};
+
+/// Something frames can be sent to: a device, a recorder, or several of them at once.
+pub trait LaserOutput {
+    fn send_samples(&mut self, buf: &[LaserdockSample]) -> Result<()>;
+}
+
+impl<T: UsbContext> LaserOutput for LaserCube<T> {
+    fn send_samples(&mut self, buf: &[LaserdockSample]) -> Result<()> {
+        LaserCube::send_samples(self, buf)
+    }
+}
+
+/// Appends everything sent to it to a CSV file in the `csv::load_points_csv` format.
+pub struct CsvRecorder {
+    file: BufWriter<File>,
+}
+
+impl CsvRecorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+        let mut file = BufWriter::new(file);
+        file.write_all(csv::HEADER.as_bytes())?;
+        Ok(Self { file })
+    }
+}
+
+impl LaserOutput for CsvRecorder {
+    fn send_samples(&mut self, buf: &[LaserdockSample]) -> Result<()> {
+        let mut text = String::new();
+        csv::write_rows(&mut text, buf);
+        self.file.write_all(text.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("{} of {total} outputs failed: {}", errors.len(), summary(errors))]
+pub struct MultiSinkError {
+    /// Index of each failed output with its error.
+    pub errors: Vec<(usize, anyhow::Error)>,
+    pub total: usize,
+}
+
+fn summary(errors: &[(usize, anyhow::Error)]) -> String {
+    errors
+        .iter()
+        .map(|(i, e)| format!("#{}: {:#}", i, e))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Tees every frame to several outputs, e.g. a device plus a `CsvRecorder`. A failing output
+/// doesn't stop the others; all failures are reported together as a `MultiSinkError`.
+#[derive(Default)]
+pub struct MultiSink {
+    outputs: Vec<Box<dyn LaserOutput + Send>>,
+}
+
+impl MultiSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, output: impl LaserOutput + Send + 'static) -> &mut Self {
+        self.outputs.push(Box::new(output));
+        self
+    }
+}
+
+impl LaserOutput for MultiSink {
+    fn send_samples(&mut self, buf: &[LaserdockSample]) -> Result<()> {
+        let errors: Vec<_> = self
+            .outputs
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, output)| output.send_samples(buf).err().map(|e| (i, e)))
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(MultiSinkError {
+                errors,
+                total: self.outputs.len(),
+            }
+            .into())
+        }
+    }
+}
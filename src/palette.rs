@@ -0,0 +1,108 @@
+use lasy::IsBlank;
+
+use crate::LaserdockSample;
+
+/// A cyclic list of colors, looked up with linear interpolation between neighbours.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Palette {
+    colors: Vec<(u8, u8, u8)>,
+}
+
+impl Palette {
+    pub fn new(colors: Vec<(u8, u8, u8)>) -> Self {
+        Self { colors }
+    }
+
+    /// `steps` fully saturated hues around the color wheel.
+    pub fn rainbow(steps: usize) -> Self {
+        let colors = (0..steps.max(1))
+            .map(|i| hue_to_rgb(i as f32 / steps.max(1) as f32))
+            .collect();
+        Self { colors }
+    }
+
+    pub fn colors(&self) -> &[(u8, u8, u8)] {
+        &self.colors
+    }
+
+    /// Color at `position`, where 0..1 covers the palette once and wraps around. An empty
+    /// palette is black.
+    pub fn at(&self, position: f32) -> (u8, u8, u8) {
+        if self.colors.is_empty() {
+            return (0, 0, 0);
+        }
+
+        let scaled = position.rem_euclid(1.0) * self.colors.len() as f32;
+        let i = scaled as usize % self.colors.len();
+        let t = scaled.fract();
+        let (r0, g0, b0) = self.colors[i];
+        let (r1, g1, b1) = self.colors[(i + 1) % self.colors.len()];
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        (mix(r0, r1), mix(g0, g1), mix(b0, b1))
+    }
+}
+
+/// Color lit points from `palette`, spread once along the path. `phase` shifts the colors along
+/// the path, with 1.0 being a full cycle, so advancing it over time makes the colors flow.
+/// Blanked points are left untouched.
+pub fn apply_palette_along(
+    points: &[LaserdockSample],
+    palette: &Palette,
+    phase: f32,
+) -> Vec<LaserdockSample> {
+    let lit = points.iter().filter(|p| !p.is_blank()).count().max(1);
+    let mut index = 0;
+
+    points
+        .iter()
+        .map(|p| {
+            if p.is_blank() {
+                return *p;
+            }
+            let (r, g, b) = palette.at(index as f32 / lit as f32 + phase);
+            index += 1;
+            p.with_rgb(r, g, b)
+        })
+        .collect()
+}
+
+/// A single color channel of the laser.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Channel {
+    R,
+    G,
+    B,
+}
+
+/// Collapse color to one channel, for single-color projectors: lit points get their luminance
+/// (0.299 R + 0.587 G + 0.114 B) on `channel` and the other channels zeroed, and stay lit however
+/// dim they are. Blanked points are left untouched.
+pub fn to_monochrome(points: &[LaserdockSample], channel: Channel) -> Vec<LaserdockSample> {
+    points
+        .iter()
+        .map(|p| {
+            if p.is_blank() {
+                return *p;
+            }
+            let (r, g, b) = p.rgb();
+            // at least 1, so dim content doesn't turn into travel moves
+            let luma = (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32)
+                .round()
+                .max(1.0) as u8;
+            match channel {
+                Channel::R => p.with_rgb(luma, 0, 0),
+                Channel::G => p.with_rgb(0, luma, 0),
+                Channel::B => p.with_rgb(0, 0, luma),
+            }
+        })
+        .collect()
+}
+
+fn hue_to_rgb(hue: f32) -> (u8, u8, u8) {
+    let channel = |offset: f32| {
+        let h = (hue + offset).rem_euclid(1.0) * 6.0;
+        let level = (h - 3.0).abs() - 1.0;
+        (level.clamp(0.0, 1.0) * 255.0).round() as u8
+    };
+    (channel(0.0), channel(2.0 / 3.0), channel(1.0 / 3.0))
+}
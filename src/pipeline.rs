@@ -0,0 +1,563 @@
+use std::{
+    collections::{hash_map::DefaultHasher, VecDeque},
+    hash::Hasher,
+    sync::{atomic::Ordering, Mutex},
+    time::{Duration, Instant},
+};
+
+use lasy::{Blanked, IsBlank, Position};
+use log::warn;
+use rusb::UsbContext;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{geometry::distance, LaserCube, LaserdockSample};
+
+/// Blanks lit samples once the beam has been held still for too long.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
+pub struct StationaryWatchdog {
+    /// Consecutive lit samples allowed at the same spot before blanking kicks in.
+    pub max_samples: usize,
+    /// Per-axis distance in DAC units within which samples count as the same spot.
+    pub tolerance: u16,
+}
+
+impl Default for StationaryWatchdog {
+    fn default() -> Self {
+        Self {
+            max_samples: 1000,
+            tolerance: 8,
+        }
+    }
+}
+
+/// Every pre-send transform in one place. `send_samples` applies them in this order: geometry
+/// (flip, scan scale), then color (blank delay, brightness and white balance, velocity
+/// compensation, persistence), then the safety clamps (combined power cap, stationary
+/// watchdog). A fresh stream gets its pre-roll prepended before any of them run. The individual
+/// setters on `LaserCube` document each option.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
+pub struct RenderConfig {
+    pub flip: (bool, bool),
+    pub scan_scale: (f32, f32),
+    pub blank_delay_samples: usize,
+    pub brightness: f32,
+    pub white_balance: [f32; 3],
+    pub velocity_compensation: f32,
+    pub persistence: f32,
+    pub max_combined_power: u16,
+    pub stationary_watchdog: Option<StationaryWatchdog>,
+    pub preroll_points: usize,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            flip: (false, false),
+            scan_scale: (1.0, 1.0),
+            blank_delay_samples: 0,
+            brightness: 1.0,
+            white_balance: [1.0; 3],
+            velocity_compensation: 0.0,
+            persistence: 0.0,
+            max_combined_power: u16::MAX,
+            stationary_watchdog: None,
+            preroll_points: 0,
+        }
+    }
+}
+
+/// The current run of lit samples at one spot: `(x, y, length)`. Carries over between
+/// `send_samples` calls since frames are usually sent batch by batch.
+type StationaryRun = (u16, u16, usize);
+
+/// Pipeline state carried from one `send_samples` call to the next. Belongs to the samples
+/// already queued on the device, so it is reset whenever the queue is dropped.
+#[derive(Default)]
+pub(crate) struct StreamState {
+    delayed_colors: Mutex<VecDeque<(u8, u8, u8)>>,
+    previous_frame: Mutex<Vec<LaserdockSample>>,
+    stationary_run: Mutex<StationaryRun>,
+}
+
+impl StreamState {
+    pub(crate) fn reset(&self, blank_delay_samples: usize) {
+        let mut colors = self.delayed_colors.lock().unwrap();
+        colors.clear();
+        colors.resize(blank_delay_samples, (0, 0, 0));
+        self.previous_frame.lock().unwrap().clear();
+        *self.stationary_run.lock().unwrap() = (0, 0, 0);
+    }
+
+    /// Push the colors of `frame` through the blank delay line, shifting them back by its length.
+    pub(crate) fn delay_colors(&self, frame: &mut [LaserdockSample]) {
+        let mut colors = self.delayed_colors.lock().unwrap();
+        for sample in frame.iter_mut() {
+            colors.push_back(sample.rgb());
+            let (r, g, b) = colors.pop_front().unwrap_or_default();
+            *sample = sample.with_rgb(r, g, b);
+        }
+    }
+}
+
+// Settings applied to every frame inside `send_samples`, in the order `process` runs them.
+impl<T: UsbContext> LaserCube<T> {
+    /// Replace all pre-send transforms at once.
+    pub fn set_render_config(&mut self, config: RenderConfig) {
+        let (flip_x, flip_y) = config.flip;
+        let [r_gain, g_gain, b_gain] = config.white_balance;
+        self.set_flip(flip_x, flip_y);
+        self.set_scan_scale(config.scan_scale.0, config.scan_scale.1);
+        self.set_blank_delay_samples(config.blank_delay_samples);
+        self.set_brightness(config.brightness);
+        self.set_white_balance(r_gain, g_gain, b_gain);
+        self.set_velocity_compensation(config.velocity_compensation);
+        self.set_persistence(config.persistence);
+        self.set_max_combined_power(config.max_combined_power);
+        self.set_stationary_watchdog(config.stationary_watchdog);
+        self.set_preroll_points(config.preroll_points);
+    }
+
+    pub fn render_config(&self) -> &RenderConfig {
+        &self.render
+    }
+
+    /// Mirror all output along X and/or Y, e.g. for ceiling or rear projection.
+    pub fn set_flip(&mut self, flip_x: bool, flip_y: bool) {
+        self.render.flip = (flip_x, flip_y);
+    }
+
+    pub fn flip(&self) -> (bool, bool) {
+        self.render.flip
+    }
+
+    /// Scale each axis independently around the center, e.g. to correct the aspect ratio when
+    /// the X and Y galvos have different effective deflection. Results outside the field are
+    /// clamped to its edge.
+    pub fn set_scan_scale(&mut self, x_scale: f32, y_scale: f32) {
+        self.render.scan_scale = (x_scale, y_scale);
+    }
+
+    pub fn scan_scale(&self) -> (f32, f32) {
+        self.render.scan_scale
+    }
+
+    /// Delay colors by this many samples relative to positions, to compensate for the galvos
+    /// lagging behind the commanded position. The beam then switches on and off where the
+    /// mirrors actually are. The delay carries across `send_samples` calls.
+    pub fn set_blank_delay_samples(&mut self, samples: usize) {
+        self.render.blank_delay_samples = samples;
+        let mut colors = self.stream.delayed_colors.lock().unwrap();
+        colors.clear();
+        colors.resize(samples, (0, 0, 0));
+    }
+
+    pub fn blank_delay_samples(&self) -> usize {
+        self.render.blank_delay_samples
+    }
+
+    /// Master gain on all three color channels. Results above 255 are clamped rather than
+    /// wrapped; see `clamped_samples`.
+    pub fn set_brightness(&mut self, brightness: f32) {
+        self.render.brightness = brightness.max(0.0);
+    }
+
+    pub fn brightness(&self) -> f32 {
+        self.render.brightness
+    }
+
+    /// Linear per-channel gains applied on top of `brightness`, to correct the white point of
+    /// unbalanced diodes.
+    pub fn set_white_balance(&mut self, r_gain: f32, g_gain: f32, b_gain: f32) {
+        self.render.white_balance = [r_gain.max(0.0), g_gain.max(0.0), b_gain.max(0.0)];
+    }
+
+    pub fn white_balance(&self) -> (f32, f32, f32) {
+        let [r, g, b] = self.render.white_balance;
+        (r, g, b)
+    }
+
+    /// Even out brightness along paths with uneven point spacing: lit samples reached by a long,
+    /// fast move are brightened and closely spaced ones dimmed, relative to the frame's average
+    /// lit spacing. `strength` is the exponent of that correction; 0 turns it off, which is the
+    /// default.
+    pub fn set_velocity_compensation(&mut self, strength: f32) {
+        self.render.velocity_compensation = strength.max(0.0);
+    }
+
+    pub fn velocity_compensation(&self) -> f32 {
+        self.render.velocity_compensation
+    }
+
+    /// Number of samples whose color had to be clamped since the last `reset_clamped_samples`.
+    pub fn clamped_samples(&self) -> usize {
+        self.clamped_samples.load(Ordering::Relaxed)
+    }
+
+    pub fn reset_clamped_samples(&self) {
+        self.clamped_samples.store(0, Ordering::Relaxed)
+    }
+
+    /// Software afterglow: each lit sample keeps the brighter of its own color and the color
+    /// previously sent at the same index, decayed by `persistence`. Blanked samples stay blanked
+    /// so travel moves never light up. Works best when every `send_samples` call is a whole frame.
+    pub fn set_persistence(&mut self, persistence: f32) {
+        self.render.persistence = persistence.clamp(0.0, 1.0);
+        self.stream.previous_frame.lock().unwrap().clear();
+    }
+
+    pub fn persistence(&self) -> f32 {
+        self.render.persistence
+    }
+
+    /// Cap on the sum of the three color channels of any one sample; brighter samples are scaled
+    /// down proportionally. Anything above `3 * 255` means no limit, which is the default.
+    pub fn set_max_combined_power(&mut self, value: u16) {
+        self.render.max_combined_power = value;
+    }
+
+    pub fn max_combined_power(&self) -> u16 {
+        self.render.max_combined_power
+    }
+
+    /// Guard against content that accidentally parks a lit beam, which can burn in or overheat.
+    /// Off by default.
+    pub fn set_stationary_watchdog(&mut self, watchdog: Option<StationaryWatchdog>) {
+        self.render.stationary_watchdog = watchdog;
+        *self.stream.stationary_run.lock().unwrap() = (0, 0, 0);
+    }
+
+    pub fn stationary_watchdog(&self) -> Option<StationaryWatchdog> {
+        self.render.stationary_watchdog
+    }
+
+    /// Start every fresh stream with `count` blanked samples at its first position, giving
+    /// sluggish galvos time to arrive before the beam turns on so the first stroke isn't
+    /// distorted. That is the first lit sample, unless the content starts with a blanked move of
+    /// its own; parking elsewhere would only pull the galvos away again. Off (0) by default.
+    pub fn set_preroll_points(&mut self, count: usize) {
+        self.render.preroll_points = count;
+    }
+
+    pub fn preroll_points(&self) -> usize {
+        self.render.preroll_points
+    }
+
+    /// Skip sending a frame that is identical to the previous one while a full copy of that is
+    /// still queued on the device, saving bus traffic for held frames. The queue depth is
+    /// estimated from the DAC rate, so this only takes effect after `set_dac_rate`. Off by
+    /// default.
+    pub fn set_skip_duplicate_frames(&mut self, skip: bool) {
+        self.skip_duplicate_frames = skip;
+        self.force_resend();
+    }
+
+    pub fn skip_duplicate_frames(&self) -> bool {
+        self.skip_duplicate_frames
+    }
+
+    /// Forget the previously sent frame so the next one is sent even if it is identical.
+    /// `clear_ringbuffer` and `reconnect` do this automatically.
+    pub fn force_resend(&self) {
+        *self.queued_frame.lock().unwrap() = None;
+    }
+
+    /// Start every fresh stream (the first send after opening or `clear_ringbuffer`) with a
+    /// blanked sample at its first position, so the beam doesn't streak in from wherever the
+    /// galvos were parked, and end `stream_iter` streams with a blanked sample. On by default.
+    pub fn set_blank_stream_edges(&mut self, blank: bool) {
+        self.blank_stream_edges = blank;
+    }
+
+    pub fn blank_stream_edges(&self) -> bool {
+        self.blank_stream_edges
+    }
+
+    /// The blanked samples to put in front of `buf` if it starts a fresh stream: the pre-roll and
+    /// the stream edge, all at the first position.
+    pub(crate) fn stream_lead_in(&self, buf: &[LaserdockSample]) -> Vec<LaserdockSample> {
+        let first = match buf.first() {
+            Some(first) if !self.stream_started.swap(true, Ordering::Relaxed) => first,
+            _ => return Vec::new(),
+        };
+
+        let count = self.render.preroll_points + usize::from(self.blank_stream_edges);
+        vec![first.blanked(); count]
+    }
+
+    pub(crate) fn is_duplicate_frame(&self, bytes: &[u8], len: usize) -> bool {
+        let rate = match self.dac_rate {
+            Some(rate) if self.skip_duplicate_frames => rate.max(1),
+            _ => return false,
+        };
+
+        let mut hasher = DefaultHasher::new();
+        hasher.write(bytes);
+        let hash = hasher.finish();
+        let scan_time = Duration::from_secs_f64(len as f64 / rate as f64);
+
+        let now = Instant::now();
+        let mut queued = self.queued_frame.lock().unwrap();
+        let queued_until = match *queued {
+            Some((previous, until)) if until > now => {
+                if previous == hash && until - now >= scan_time {
+                    return true;
+                }
+                until
+            }
+            _ => now,
+        };
+        *queued = Some((hash, queued_until + scan_time));
+        false
+    }
+
+    pub(crate) fn processing_active(&self) -> bool {
+        self.render.flip != (false, false)
+            || self.render.scan_scale != (1.0, 1.0)
+            || self.render.blank_delay_samples > 0
+            || self.gains() != [1.0; 3]
+            || self.render.velocity_compensation > 0.0
+            || self.render.persistence > 0.0
+            || self.combined_power_limited()
+            || self.render.stationary_watchdog.is_some()
+    }
+
+    pub(crate) fn process(&self, frame: &mut [LaserdockSample]) {
+        if self.render.flip != (false, false) {
+            let (flip_x, flip_y) = self.render.flip;
+            for sample in frame.iter_mut() {
+                if flip_x {
+                    sample.x = sample.x.flip();
+                }
+                if flip_y {
+                    sample.y = sample.y.flip();
+                }
+            }
+        }
+        if self.render.scan_scale != (1.0, 1.0) {
+            let (x_scale, y_scale) = self.render.scan_scale;
+            for sample in frame.iter_mut() {
+                let [x, y] = sample.position();
+                *sample = sample.with_position(x * x_scale, y * y_scale);
+            }
+        }
+        if self.render.blank_delay_samples > 0 {
+            self.stream.delay_colors(frame);
+        }
+        let gains = self.gains();
+        if gains != [1.0; 3] {
+            self.scale_colors(frame, gains);
+        }
+        if self.render.velocity_compensation > 0.0 {
+            self.apply_velocity_compensation(frame);
+        }
+        if self.render.persistence > 0.0 {
+            self.apply_persistence(frame);
+        }
+        if self.combined_power_limited() {
+            self.apply_max_combined_power(frame);
+        }
+        if let Some(watchdog) = self.render.stationary_watchdog {
+            self.apply_stationary_watchdog(frame, watchdog);
+        }
+    }
+
+    /// Per-channel gains: white balance times `brightness`, reduced while an
+    /// `enable_output_ramped` fade-in is running.
+    fn gains(&self) -> [f32; 3] {
+        let gain = match self.output_ramp {
+            Some((start, duration)) if duration > Duration::ZERO => {
+                let ramp = start.elapsed().as_secs_f32() / duration.as_secs_f32();
+                self.render.brightness * ramp.min(1.0)
+            }
+            _ => self.render.brightness,
+        };
+        self.render.white_balance.map(|balance| balance * gain)
+    }
+
+    fn scale_colors(&self, frame: &mut [LaserdockSample], gains: [f32; 3]) {
+        let mut clamped = 0;
+        for sample in frame.iter_mut() {
+            let (r, g, b) = sample.rgb();
+            let (r, r_clamped) = scale_channel(r, gains[0]);
+            let (g, g_clamped) = scale_channel(g, gains[1]);
+            let (b, b_clamped) = scale_channel(b, gains[2]);
+            if r_clamped || g_clamped || b_clamped {
+                clamped += 1;
+            }
+            *sample = sample.with_rgb(r, g, b);
+        }
+
+        if clamped > 0 {
+            self.clamped_samples.fetch_add(clamped, Ordering::Relaxed);
+        }
+    }
+
+    fn apply_velocity_compensation(&self, frame: &mut [LaserdockSample]) {
+        let spacing: Vec<f32> = (0..frame.len())
+            .map(|i| match i.checked_sub(1) {
+                Some(prev) if !frame[i].is_blank() => distance(&frame[prev], &frame[i]),
+                _ => 0.0,
+            })
+            .collect();
+        let lit = frame.iter().skip(1).filter(|p| !p.is_blank()).count();
+        if lit == 0 {
+            return;
+        }
+        let mean = spacing.iter().sum::<f32>() / lit as f32;
+        if mean == 0.0 {
+            return;
+        }
+
+        let mut clamped = 0;
+        for (sample, d) in frame.iter_mut().zip(spacing).skip(1) {
+            if sample.is_blank() {
+                continue;
+            }
+            // a dwell (d = 0) ends up at half the gain of an average move, not dark
+            let gain = ((d + mean) / (2.0 * mean)).powf(self.render.velocity_compensation);
+            let (r, g, b) = sample.rgb();
+            let (r, r_clamped) = scale_channel(r, gain);
+            let (g, g_clamped) = scale_channel(g, gain);
+            let (b, b_clamped) = scale_channel(b, gain);
+            if r_clamped || g_clamped || b_clamped {
+                clamped += 1;
+            }
+            *sample = sample.with_rgb(r, g, b);
+        }
+
+        if clamped > 0 {
+            self.clamped_samples.fetch_add(clamped, Ordering::Relaxed);
+        }
+    }
+
+    fn apply_persistence(&self, frame: &mut [LaserdockSample]) {
+        let mut previous = self.stream.previous_frame.lock().unwrap();
+        let decay = |c: u8| (c as f32 * self.render.persistence) as u8;
+
+        for (sample, prev) in frame.iter_mut().zip(previous.iter()) {
+            if !sample.is_blank() {
+                let (r, g, b) = sample.rgb();
+                let (pr, pg, pb) = prev.rgb();
+                *sample = sample.with_rgb(r.max(decay(pr)), g.max(decay(pg)), b.max(decay(pb)));
+            }
+        }
+
+        previous.clear();
+        previous.extend_from_slice(frame);
+    }
+
+    fn combined_power_limited(&self) -> bool {
+        self.render.max_combined_power < 3 * u8::MAX as u16
+    }
+
+    fn apply_max_combined_power(&self, frame: &mut [LaserdockSample]) {
+        let limit = self.render.max_combined_power as f32;
+        for sample in frame.iter_mut() {
+            let (r, g, b) = sample.rgb();
+            let sum = r as f32 + g as f32 + b as f32;
+            if sum > limit {
+                let gain = limit / sum;
+                let scale = |c: u8| (c as f32 * gain) as u8;
+                *sample = sample.with_rgb(scale(r), scale(g), scale(b));
+            }
+        }
+    }
+
+    fn apply_stationary_watchdog(
+        &self,
+        frame: &mut [LaserdockSample],
+        watchdog: StationaryWatchdog,
+    ) {
+        let mut run = self.stream.stationary_run.lock().unwrap();
+        let mut blanked = 0;
+
+        for sample in frame.iter_mut() {
+            if sample.is_blank() {
+                *run = (0, 0, 0);
+                continue;
+            }
+
+            let (x, y, len) = *run;
+            let near =
+                |a: u16, b: u16| (a as i32 - b as i32).unsigned_abs() <= watchdog.tolerance as u32;
+            if len > 0 && near(x, sample.x.0) && near(y, sample.y.0) {
+                run.2 += 1;
+            } else {
+                *run = (sample.x.0, sample.y.0, 1);
+            }
+
+            if run.2 > watchdog.max_samples {
+                *sample = sample.blanked();
+                blanked += 1;
+            }
+        }
+
+        if blanked > 0 {
+            warn!("stationary beam: blanked {} samples", blanked);
+        }
+    }
+}
+
+/// Scale a color channel, clamping to 0..=255. The flag reports whether clamping happened.
+pub(crate) fn scale_channel(c: u8, gain: f32) -> (u8, bool) {
+    let scaled = (c as f32 * gain).round();
+    if scaled > u8::MAX as f32 {
+        (u8::MAX, true)
+    } else {
+        (scaled.max(0.0) as u8, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset_drops_the_previous_scene() {
+        let state = StreamState::default();
+        state.reset(3);
+        state.delayed_colors.lock().unwrap().push_back((255, 0, 0));
+        state
+            .previous_frame
+            .lock()
+            .unwrap()
+            .push(LaserdockSample::new(255, 0, 0, 0., 0.));
+        *state.stationary_run.lock().unwrap() = (1, 2, 3);
+
+        state.reset(3);
+
+        assert_eq!(
+            state
+                .delayed_colors
+                .lock()
+                .unwrap()
+                .iter()
+                .collect::<Vec<_>>(),
+            vec![&(0, 0, 0); 3]
+        );
+        assert!(state.previous_frame.lock().unwrap().is_empty());
+        assert_eq!(*state.stationary_run.lock().unwrap(), (0, 0, 0));
+    }
+
+    #[test]
+    fn blank_batch_after_reset_stays_blank() {
+        let state = StreamState::default();
+        state.reset(2);
+        let mut lit = [LaserdockSample::new(255, 0, 0, 0.5, 0.5); 4];
+        state.delay_colors(&mut lit);
+
+        // the delay line still holds lit colors, which would light up the next batch
+        let mut blank = [LaserdockSample::new(0, 0, 0, 0., 0.); 4];
+        state.delay_colors(&mut blank);
+        assert!(!blank[0].is_blank());
+
+        state.delay_colors(&mut lit);
+        state.reset(2);
+        let mut blank = [LaserdockSample::new(0, 0, 0, 0., 0.); 4];
+        state.delay_colors(&mut blank);
+        assert!(blank.iter().all(|p| p.is_blank()));
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+#[cfg(feature = "serde")]
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use rusb::UsbContext;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{LaserCube, RenderConfig};
+
+/// Per-projector settings, applied in one go with `LaserCube::apply_profile`. The render
+/// settings are stored inline, next to `dac_rate`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
+pub struct CalibrationProfile {
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub render: RenderConfig,
+    pub dac_rate: Option<u32>,
+}
+
+/// Profiles for a whole rig, keyed by device serial number.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CalibrationProfiles {
+    pub by_serial: HashMap<String, CalibrationProfile>,
+}
+
+#[cfg(feature = "serde")]
+impl CalibrationProfile {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        Ok(fs::write(path, toml::to_string_pretty(self)?)?)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl CalibrationProfiles {
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        Ok(fs::write(path, toml::to_string_pretty(self)?)?)
+    }
+}
+
+impl<T: UsbContext> LaserCube<T> {
+    pub fn apply_profile(&mut self, profile: &CalibrationProfile) -> Result<()> {
+        self.set_render_config(profile.render);
+        if let Some(rate) = profile.dac_rate {
+            self.set_dac_rate(rate)?;
+        }
+        Ok(())
+    }
+
+    /// Apply the profile stored under this device's serial number, if there is one.
+    pub fn apply_profile_for_serial(&mut self, profiles: &CalibrationProfiles) -> Result<bool> {
+        let serial = self.serial_number()?;
+        match profiles.by_serial.get(&serial) {
+            Some(profile) => {
+                self.apply_profile(profile)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+};
+
+use rusb::UsbContext;
+
+use crate::{animation::Frame, LaserCube};
+
+/// What `FrameQueue::push` does when the queue is full.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// Wait until the sender has taken a frame. Once the queue is closed nobody will, so the
+    /// frame being pushed is discarded instead.
+    Block,
+    /// Discard the oldest queued frame to make room.
+    DropOldest,
+    /// Discard the frame being pushed.
+    DropNewest,
+}
+
+struct QueueState {
+    frames: VecDeque<Frame>,
+    closed: bool,
+}
+
+/// Bounded hand-off between a real-time frame source and a background sender, so latency stays
+/// bounded when the laser can't keep up.
+#[derive(Clone)]
+pub struct FrameQueue {
+    shared: Arc<(Mutex<QueueState>, Condvar)>,
+    capacity: usize,
+    policy: QueuePolicy,
+}
+
+impl FrameQueue {
+    pub fn new(capacity: usize, policy: QueuePolicy) -> Self {
+        let state = QueueState {
+            frames: VecDeque::with_capacity(capacity),
+            closed: false,
+        };
+
+        Self {
+            shared: Arc::new((Mutex::new(state), Condvar::new())),
+            capacity: capacity.max(1),
+            policy,
+        }
+    }
+
+    /// Queue a frame. Returns `true` if a frame (this one or an older one) was dropped.
+    pub fn push(&self, frame: Frame) -> bool {
+        let (lock, cvar) = &*self.shared;
+        let mut state = lock.lock().unwrap();
+        let mut dropped = false;
+
+        if state.frames.len() >= self.capacity {
+            match self.policy {
+                QueuePolicy::Block => {
+                    state = cvar
+                        .wait_while(state, |s| s.frames.len() >= self.capacity && !s.closed)
+                        .unwrap();
+                    if state.frames.len() >= self.capacity {
+                        return true;
+                    }
+                }
+                QueuePolicy::DropOldest => {
+                    state.frames.pop_front();
+                    dropped = true;
+                }
+                QueuePolicy::DropNewest => return true,
+            }
+        }
+
+        state.frames.push_back(frame);
+        cvar.notify_all();
+        dropped
+    }
+
+    /// Take the oldest frame without waiting.
+    pub fn try_pop(&self) -> Option<Frame> {
+        let (lock, cvar) = &*self.shared;
+        let frame = lock.lock().unwrap().frames.pop_front();
+        cvar.notify_all();
+        frame
+    }
+
+    /// Wait for the next frame. Returns `None` once the queue is closed and drained.
+    pub fn pop(&self) -> Option<Frame> {
+        let (lock, cvar) = &*self.shared;
+        let mut state = cvar
+            .wait_while(lock.lock().unwrap(), |s| s.frames.is_empty() && !s.closed)
+            .unwrap();
+        let frame = state.frames.pop_front();
+        cvar.notify_all();
+        frame
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.0.lock().unwrap().frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Wake everyone up and let the sender exit once the queued frames are drawn.
+    pub fn close(&self) {
+        let (lock, cvar) = &*self.shared;
+        lock.lock().unwrap().closed = true;
+        cvar.notify_all();
+    }
+
+    /// Draw queued frames on a background thread. While the queue is empty the last frame is
+    /// redrawn so the image doesn't vanish. The device is handed back when the queue is closed.
+    pub fn spawn_sender<T: UsbContext + 'static>(
+        &self,
+        device: LaserCube<T>,
+    ) -> JoinHandle<anyhow::Result<LaserCube<T>>> {
+        let queue = self.clone();
+        thread::spawn(move || {
+            let mut current = None;
+            loop {
+                match queue.try_pop() {
+                    Some(frame) => current = Some(frame),
+                    None if current.is_none() => match queue.pop() {
+                        Some(frame) => current = Some(frame),
+                        None => return Ok(device),
+                    },
+                    None if queue.is_closed() => return Ok(device),
+                    None => {}
+                }
+
+                if let Some(frame) = &current {
+                    frame.draw(&device)?;
+                }
+            }
+        })
+    }
+
+    fn is_closed(&self) -> bool {
+        self.shared.0.lock().unwrap().closed
+    }
+}
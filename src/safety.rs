@@ -0,0 +1,158 @@
+use std::{
+    panic,
+    sync::{Arc, Mutex, Once, Weak},
+    time::Duration,
+};
+
+use log::warn;
+use rusb::{DeviceHandle, UsbContext};
+
+use crate::{wire_bytes, LaserCube, LaserdockSample, SetCommand, RECV_BUF_LEN, SAMPLES_PER_BATCH};
+
+// Erases the libusb context type so devices opened through different contexts share one registry.
+pub(crate) trait BulkDevice: Send + Sync {
+    fn write_bulk(&self, endpoint: u8, buf: &[u8], timeout: Duration) -> rusb::Result<usize>;
+    fn read_bulk(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> rusb::Result<usize>;
+    fn release_interface(&mut self, iface: u8) -> rusb::Result<()>;
+}
+
+impl<T: UsbContext> BulkDevice for DeviceHandle<T> {
+    fn write_bulk(&self, endpoint: u8, buf: &[u8], timeout: Duration) -> rusb::Result<usize> {
+        DeviceHandle::write_bulk(self, endpoint, buf, timeout)
+    }
+
+    fn read_bulk(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> rusb::Result<usize> {
+        DeviceHandle::read_bulk(self, endpoint, buf, timeout)
+    }
+
+    fn release_interface(&mut self, iface: u8) -> rusb::Result<()> {
+        DeviceHandle::release_interface(self, iface)
+    }
+}
+
+// Every opened device registers its endpoints here. Entries hold weak references, so
+// registration doesn't keep a device open.
+struct Registered {
+    device: Weak<dyn BulkDevice>,
+    control_read: u8,
+    control_write: u8,
+    data_write: u8,
+}
+
+impl Registered {
+    fn beam_off(&self) {
+        if let Some(device) = self.device.upgrade() {
+            let timeout = Duration::from_millis(100);
+            let blank = [LaserdockSample::new(0, 0, 0, 0., 0.); SAMPLES_PER_BATCH];
+            let disable = [SetCommand::EnableOutput as u8, 0];
+            let _ = device.write_bulk(self.data_write, &wire_bytes(&blank), timeout);
+            let _ = device.write_bulk(self.control_write, &disable, timeout);
+            let mut recv = [0; RECV_BUF_LEN];
+            let _ = device.read_bulk(self.control_read, &mut recv, timeout);
+        }
+    }
+}
+
+static REGISTRY: Mutex<Vec<Registered>> = Mutex::new(Vec::new());
+static INSTALL: Once = Once::new();
+
+pub(crate) fn register<D: BulkDevice + 'static>(
+    device: &Arc<D>,
+    control_read: u8,
+    control_write: u8,
+    data_write: u8,
+) {
+    let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    registry.retain(|registered| registered.device.strong_count() > 0);
+    let device: Weak<D> = Arc::downgrade(device);
+    registry.push(Registered {
+        device,
+        control_read,
+        control_write,
+        data_write,
+    });
+}
+
+/// Unregister `device` and release `interfaces` so a new handle can claim them. The registry's
+/// weak reference would otherwise keep `Arc::get_mut` from ever succeeding.
+pub(crate) fn release<D: BulkDevice + 'static>(device: &mut Arc<D>, interfaces: &[u8]) {
+    {
+        let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+        let ptr = Arc::as_ptr(device).cast::<()>();
+        registry.retain(|registered| {
+            registered.device.strong_count() > 0 && registered.device.as_ptr().cast::<()>() != ptr
+        });
+    }
+
+    match Arc::get_mut(device) {
+        Some(device) => {
+            for &iface in interfaces {
+                let _ = device.release_interface(iface);
+            }
+        }
+        None => warn!("device handle is shared, can't release its interfaces"),
+    }
+}
+
+impl LaserCube {
+    /// Opt in to a panic hook that blanks and disables every open LaserCube before handing over
+    /// to the previously installed hook. Installing more than once has no further effect.
+    pub fn install_panic_safety() {
+        INSTALL.call_once(|| {
+            let previous = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                // try_lock: the panic may have happened while the registry was locked
+                if let Ok(registry) = REGISTRY.try_lock() {
+                    for registered in registry.iter() {
+                        registered.beam_off();
+                    }
+                }
+                previous(info);
+            }));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    struct MockDevice {
+        released: Arc<AtomicUsize>,
+    }
+
+    impl BulkDevice for MockDevice {
+        fn write_bulk(&self, _: u8, buf: &[u8], _: Duration) -> rusb::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn read_bulk(&self, _: u8, buf: &mut [u8], _: Duration) -> rusb::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn release_interface(&mut self, _: u8) -> rusb::Result<()> {
+            self.released.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn release_works_on_a_registered_device() {
+        let released = Arc::new(AtomicUsize::new(0));
+        let mut device = Arc::new(MockDevice {
+            released: released.clone(),
+        });
+        register(&device, 1, 2, 3);
+
+        release(&mut device, &[0, 1]);
+
+        assert_eq!(released.load(Ordering::SeqCst), 2);
+        let registry = REGISTRY.lock().unwrap();
+        let ptr = Arc::as_ptr(&device).cast::<()>();
+        assert!(registry
+            .iter()
+            .all(|registered| registered.device.as_ptr().cast::<()>() != ptr));
+    }
+}
@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+/// Data endpoint transfer statistics, collected while enabled via `LaserCube::enable_stats`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TransferStats {
+    pub bytes_sent: u64,
+    pub transfers: u64,
+    pub short_writes: u64,
+    pub min_transfer_time: Option<Duration>,
+    pub max_transfer_time: Option<Duration>,
+    pub total_transfer_time: Duration,
+}
+
+impl TransferStats {
+    pub fn avg_transfer_time(&self) -> Option<Duration> {
+        if self.transfers == 0 {
+            return None;
+        }
+        Some(self.total_transfer_time / self.transfers as u32)
+    }
+
+    pub(crate) fn record(&mut self, requested: usize, written: usize, elapsed: Duration) {
+        self.bytes_sent += written as u64;
+        self.transfers += 1;
+        if written != requested {
+            self.short_writes += 1;
+        }
+        self.min_transfer_time = Some(self.min_transfer_time.map_or(elapsed, |t| t.min(elapsed)));
+        self.max_transfer_time = Some(self.max_transfer_time.map_or(elapsed, |t| t.max(elapsed)));
+        self.total_transfer_time += elapsed;
+    }
+}
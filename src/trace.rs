@@ -0,0 +1,96 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use image::{imageops::FilterType, GrayImage};
+use lasy::Blanked;
+use rusb::UsbContext;
+
+use crate::{LaserCube, LaserdockSample};
+
+// Larger images are scaled down before tracing to keep the point count drawable.
+const MAX_TRACE_SIZE: u32 = 128;
+const THRESHOLD: u8 = 128;
+const SHOW_IMAGE_DAC_RATE: u32 = 30_000;
+
+/// Outline the bright regions of `image` (luma at least 128) as one path through all edge
+/// pixels, fitted to the scan field with the aspect ratio kept. Jumps between separate outlines
+/// are blanked. Empty if the image has no edges.
+pub fn trace_edges(image: &GrayImage, color: (u8, u8, u8)) -> Vec<LaserdockSample> {
+    let (width, height) = image.dimensions();
+    let lit = |x: i64, y: i64| {
+        x >= 0
+            && y >= 0
+            && x < width as i64
+            && y < height as i64
+            && image.get_pixel(x as u32, y as u32).0[0] >= THRESHOLD
+    };
+
+    let mut edges: Vec<(i64, i64)> = image
+        .enumerate_pixels()
+        .map(|(x, y, _)| (x as i64, y as i64))
+        .filter(|&(x, y)| {
+            lit(x, y)
+                && [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                    .iter()
+                    .any(|(dx, dy)| !lit(x + dx, y + dy))
+        })
+        .collect();
+
+    let scale = 2. / width.max(height).max(1) as f64;
+    let (r, g, b) = color;
+    let to_sample = |(x, y): (i64, i64)| {
+        LaserdockSample::new(
+            r,
+            g,
+            b,
+            (x as f64 - width as f64 / 2.) * scale,
+            (height as f64 / 2. - y as f64) * scale,
+        )
+    };
+
+    // greedy nearest-neighbour walk through the edge pixels
+    let mut points = vec![];
+    let mut current = match edges.pop() {
+        Some(start) => start,
+        None => return points,
+    };
+    points.push(to_sample(current).blanked());
+    points.push(to_sample(current));
+    while !edges.is_empty() {
+        let (i, _) = edges
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, &(x, y))| (x - current.0).pow(2) + (y - current.1).pow(2))
+            .unwrap();
+        let next = edges.swap_remove(i);
+        if (next.0 - current.0).abs() > 1 || (next.1 - current.1).abs() > 1 {
+            points.push(to_sample(current).blanked());
+            points.push(to_sample(next).blanked());
+        }
+        points.push(to_sample(next));
+        current = next;
+    }
+    points.push(to_sample(current).blanked());
+
+    points
+}
+
+impl<T: UsbContext> LaserCube<T> {
+    /// Trace the outlines of an image file and draw them until an error occurs.
+    pub fn show_image(&mut self, path: &Path) -> Result<()> {
+        let image = image::open(path)
+            .with_context(|| format!("loading {}", path.display()))?
+            .resize(MAX_TRACE_SIZE, MAX_TRACE_SIZE, FilterType::Triangle)
+            .to_luma8();
+
+        let points = trace_edges(&image, (0, 255, 0));
+        if points.is_empty() {
+            return Err(anyhow!("{} has no outlines to trace", path.display()));
+        }
+
+        self.set_dac_rate(SHOW_IMAGE_DAC_RATE)?;
+        loop {
+            self.draw_once(&points)?;
+        }
+    }
+}
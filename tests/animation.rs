@@ -0,0 +1,175 @@
+use lasercube::{
+    animation::{frame_diff, Animation, Frame},
+    LaserdockSample,
+};
+use lasy::{IsBlank, Position};
+
+fn line(y: f64, r: u8) -> Vec<LaserdockSample> {
+    (0..=4)
+        .map(|i| LaserdockSample::new(r, 0, 0, i as f64 / 2. - 1., y))
+        .collect()
+}
+
+#[test]
+fn transitions_are_blanked_between_every_pair_of_frames() {
+    let originals = [line(0.5, 100), line(0.0, 150), line(-0.5, 200)];
+    let frames = originals.iter().cloned().map(Frame::new).collect();
+    let animation = Animation::new(frames, 10);
+    let frames = animation.frames();
+
+    for (i, original) in originals.iter().enumerate() {
+        let next = &originals[(i + 1) % originals.len()];
+        let points = frames[i].points();
+
+        // original content, then 2 dwell points at its end, then blanked travel ending in 2
+        // points at the next frame's start
+        assert!(points.len() >= original.len() + 4, "frame {}", i);
+
+        let end = original.last().unwrap();
+        let tail = &points[original.len()..];
+        for dwell in &tail[..2] {
+            assert_eq!(dwell.position(), end.position(), "frame {}", i);
+            assert!(!dwell.is_blank(), "frame {}", i);
+        }
+        assert!(tail[2..].iter().all(|p| p.is_blank()), "frame {}", i);
+        for blank in &tail[tail.len() - 2..] {
+            assert_eq!(blank.position(), next[0].position(), "frame {}", i);
+        }
+        for pair in tail[1..].windows(2) {
+            let [ax, ay] = pair[0].position();
+            let [bx, by] = pair[1].position();
+            let step = ((bx - ax).powi(2) + (by - ay).powi(2)).sqrt();
+            assert!(step <= Animation::MAX_BLANK_STEP + 1e-3, "frame {}", i);
+        }
+    }
+}
+
+#[test]
+fn single_frame_is_left_alone() {
+    let animation = Animation::new(vec![Frame::new(line(0.0, 100))], 10);
+
+    assert_eq!(animation.frames()[0].points().len(), 5);
+}
+
+#[test]
+fn empty_frames_are_dropped() {
+    let frames = vec![
+        Frame::new(vec![]),
+        Frame::new(line(0.5, 100)),
+        Frame::new(vec![]),
+        Frame::new(line(-0.5, 200)),
+    ];
+    let animation = Animation::new(frames, 10);
+
+    assert_eq!(animation.frames().len(), 2);
+    assert!(animation.frames().iter().all(|f| !f.content().is_empty()));
+}
+
+#[test]
+fn all_empty_frames_give_an_empty_animation() {
+    let animation = Animation::new(vec![Frame::new(vec![]), Frame::new(vec![])], 10);
+
+    assert!(animation.frames().is_empty());
+}
+
+#[test]
+fn single_point_frames_get_transitions() {
+    let a = LaserdockSample::new(100, 0, 0, -0.5, 0.);
+    let b = LaserdockSample::new(0, 100, 0, 0.5, 0.);
+    let animation = Animation::new(vec![Frame::new(vec![a]), Frame::new(vec![b])], 10);
+    let frames = animation.frames();
+
+    assert_eq!(frames[0].content(), &[a]);
+    assert_eq!(frames[0].points()[1], a);
+    let last = frames[0].points().last().unwrap();
+    assert!(last.is_blank());
+    assert_eq!(last.position(), b.position());
+}
+
+#[test]
+fn single_point_single_frame_is_left_alone() {
+    let a = LaserdockSample::new(100, 0, 0, 0., 0.);
+    let animation = Animation::new(vec![Frame::new(vec![a])], 10);
+
+    assert_eq!(animation.frames()[0].points(), &[a]);
+}
+
+#[test]
+fn chain_keeps_segment_delays_and_blanks_the_seams() {
+    let a = Animation::new(
+        vec![Frame::new(line(0.5, 100)), Frame::new(line(0.0, 100))],
+        10,
+    );
+    let b = Animation::new(vec![Frame::new(line(-0.5, 200))], 40);
+    let chained = a.chain(b);
+    let frames = chained.frames();
+
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames[0].delay_ms(), None);
+    assert_eq!(frames[2].delay_ms(), Some(40));
+    for (i, frame) in frames.iter().enumerate() {
+        let next_start = frames[(i + 1) % frames.len()].points()[0];
+        let last = frame.points().last().unwrap();
+        assert!(last.is_blank(), "frame {}", i);
+        assert_eq!(last.position(), next_start.position(), "frame {}", i);
+    }
+}
+
+#[test]
+fn map_keeps_transitions_blanked() {
+    let frames = vec![Frame::new(line(0.5, 100)), Frame::new(line(-0.5, 200))];
+    let animation = Animation::new(frames, 10);
+
+    for frame in animation.frames() {
+        let recolored = frame.map(|p| p.with_rgb(255, 0, 0));
+        let blanks = frame.points().iter().map(|p| p.is_blank());
+
+        assert_eq!(recolored.points().len(), frame.points().len());
+        for (p, was_blank) in recolored.points().iter().zip(blanks) {
+            assert_eq!(p.is_blank(), was_blank);
+        }
+        assert!(recolored.content().iter().all(|p| p.rgb() == (255, 0, 0)));
+    }
+}
+
+#[test]
+fn preview_keeps_delay_and_dac_rate() {
+    let mut frame = Frame::with_delay(line(0.0, 100), 25);
+    frame.set_dac_rate(Some(20_000));
+    let preview = frame.preview_stride(2);
+
+    assert!(preview.points().len() < frame.points().len());
+    assert_eq!(preview.delay_ms(), Some(25));
+    assert_eq!(preview.dac_rate(), Some(20_000));
+}
+
+fn point(r: u8, x: f64) -> Frame {
+    Frame::new(vec![LaserdockSample::new(r, 0, 0, x, 0.)])
+}
+
+#[test]
+fn frame_diff_lit_to_lit_is_not_blanked() {
+    let diff = frame_diff(&point(100, -0.5), &point(100, 0.5));
+
+    assert!(!diff.blanked);
+    assert!((diff.lit_gap.unwrap() - 1.0).abs() < 1e-3);
+}
+
+#[test]
+fn frame_diff_blank_to_lit_is_not_blanked() {
+    assert!(!frame_diff(&point(0, -0.5), &point(100, 0.5)).blanked);
+    assert!(!frame_diff(&point(100, -0.5), &point(0, 0.5)).blanked);
+}
+
+#[test]
+fn frame_diff_blank_to_blank_is_blanked() {
+    let diff = frame_diff(&point(0, -0.5), &point(0, 0.5));
+
+    assert!(diff.blanked);
+    assert_eq!(diff.lit_gap, None);
+}
+
+#[test]
+fn frame_diff_without_travel_is_blanked() {
+    assert!(frame_diff(&point(100, 0.5), &point(200, 0.5)).blanked);
+}
@@ -0,0 +1,72 @@
+use lasercube::{
+    csv::{load_points_csv, parse_points_csv, save_points_csv},
+    LaserdockSample,
+};
+use lasy::{IsBlank, Position};
+
+fn error(text: &str) -> String {
+    format!("{:#}", parse_points_csv(text).unwrap_err())
+}
+
+#[test]
+fn parses_rows_with_any_separator() {
+    let points = parse_points_csv("-1,1,255,0,0\n0.5\t-0.5\t0 128 0\n0, 0, 0, 0, 0\n").unwrap();
+
+    assert_eq!(points.len(), 3);
+    assert_eq!(points[0].rgb(), (255, 0, 0));
+    assert_eq!(points[0].position(), [-1., 1.]);
+    assert_eq!(points[1].rgb(), (0, 128, 0));
+    assert!(points[2].is_blank());
+}
+
+#[test]
+fn header_comments_and_blank_lines_are_skipped() {
+    let text = "# x,y,r,g,b\n\n  \n0,0,1,2,3 # center\n# 1,1,1,1,1\n";
+    let points = parse_points_csv(text).unwrap();
+
+    assert_eq!(points, [LaserdockSample::new(1, 2, 3, 0., 0.)]);
+}
+
+#[test]
+fn empty_input_has_no_points() {
+    assert!(parse_points_csv("").unwrap().is_empty());
+    assert!(parse_points_csv("# x,y,r,g,b\n").unwrap().is_empty());
+}
+
+#[test]
+fn wrong_field_count_is_an_error() {
+    assert!(error("0,0,0,0,0\n0,0,0,0\n").contains("line 2: expected 5 fields"));
+    assert!(error("0,0,0,0,0,0").contains("got 6"));
+}
+
+#[test]
+fn malformed_values_are_errors() {
+    assert!(error("x,0,0,0,0").contains("line 1: bad coordinate \"x\""));
+    assert!(error("0,0,red,0,0").contains("line 1: bad color \"red\""));
+    assert!(error("0,0,0,-1,0").contains("bad color \"-1\""));
+}
+
+#[test]
+fn out_of_range_values_are_errors() {
+    assert!(error("0,0,256,0,0").contains("bad color \"256\""));
+    assert!(error("1.5,0,0,0,0").contains("coordinate 1.5 outside -1..1"));
+    assert!(error("0,-1.01,0,0,0").contains("outside -1..1"));
+    assert!(error("NaN,0,0,0,0").contains("outside -1..1"));
+    assert!(error("0,inf,0,0,0").contains("outside -1..1"));
+}
+
+#[test]
+fn save_and_load_round_trip() {
+    let points = vec![
+        LaserdockSample::new(0, 0, 0, -1., -1.),
+        LaserdockSample::new(255, 128, 1, 0.25, 0.75),
+        LaserdockSample::new(10, 20, 30, 1., 1.),
+    ];
+    let path = std::env::temp_dir().join(format!("lasercube-points-{}.csv", std::process::id()));
+
+    save_points_csv(&path, &points).unwrap();
+    let loaded = load_points_csv(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.unwrap(), points);
+}
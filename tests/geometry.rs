@@ -0,0 +1,170 @@
+use lasercube::{
+    geometry::{
+        blank_bracketed, dedup_consecutive, dedup_consecutive_keep, densify, kaleidoscope,
+        kaleidoscope_mirrored, limit_velocity, normalize_point_count, thicken,
+    },
+    LaserdockSample,
+};
+use lasy::{IsBlank, Position};
+
+fn lit(x: f64, y: f64) -> LaserdockSample {
+    LaserdockSample::new(255, 0, 0, x, y)
+}
+
+fn blank(x: f64, y: f64) -> LaserdockSample {
+    LaserdockSample::new(0, 0, 0, x, y)
+}
+
+fn assert_at(sample: &LaserdockSample, x: f32, y: f32) {
+    let [px, py] = sample.position();
+    assert!(
+        (px - x).abs() < 2e-3 && (py - y).abs() < 2e-3,
+        "{:?} is not at ({}, {})",
+        sample.position(),
+        x,
+        y
+    );
+}
+
+fn max_step(points: &[LaserdockSample]) -> f32 {
+    points
+        .windows(2)
+        .map(|pair| {
+            let ([ax, ay], [bx, by]) = (pair[0].position(), pair[1].position());
+            ((bx - ax).powi(2) + (by - ay).powi(2)).sqrt()
+        })
+        .fold(0., f32::max)
+}
+
+#[test]
+fn densify_fills_lit_moves_only() {
+    let points = [lit(-0.5, 0.), lit(0.5, 0.), blank(0.5, 1.)];
+    let dense = densify(&points, 0.25);
+
+    assert_eq!(dense.len(), 6);
+    assert!(dense[..5].iter().all(|p| !p.is_blank()));
+    assert!(max_step(&dense[..5]) <= 0.25 + 1e-3);
+    assert_eq!(dense[5], points[2]);
+}
+
+#[test]
+fn limit_velocity_blanks_moves_touching_a_blank() {
+    let points = [lit(0., 0.), blank(0., 1.)];
+    let limited = limit_velocity(&points, 0.25);
+
+    assert_eq!(limited.len(), 5);
+    assert!(limited[1..].iter().all(|p| p.is_blank()));
+    assert!(max_step(&limited) <= 0.25 + 1e-3);
+}
+
+#[test]
+fn subdividing_trivial_input_is_a_no_op() {
+    assert!(densify(&[], 0.1).is_empty());
+    assert!(limit_velocity(&[], 0.1).is_empty());
+    assert_eq!(densify(&[lit(0.5, 0.5)], 0.1), [lit(0.5, 0.5)]);
+    assert_eq!(limit_velocity(&[lit(0.5, 0.5)], 0.1), [lit(0.5, 0.5)]);
+}
+
+#[test]
+fn thicken_draws_offset_passes() {
+    let run = [lit(-0.5, 0.), lit(0.5, 0.)];
+    let thick = thicken(&run, 0.2, 3);
+
+    // every pass is bracketed by blanks
+    assert_eq!(thick.len(), 3 * 4);
+    let lit_points: Vec<_> = thick.iter().filter(|p| !p.is_blank()).collect();
+    assert_at(lit_points[0], -0.5, -0.1);
+    assert_at(lit_points[1], 0.5, -0.1);
+    // the middle pass runs backwards
+    assert_at(lit_points[2], 0.5, 0.);
+    assert_at(lit_points[3], -0.5, 0.);
+    assert_at(lit_points[4], -0.5, 0.1);
+}
+
+#[test]
+fn thicken_handles_trivial_input() {
+    assert!(thicken(&[], 0.2, 3).is_empty());
+    assert_eq!(thicken(&[blank(0., 0.)], 0.2, 3), [blank(0., 0.)]);
+
+    let single = thicken(&[lit(0.5, 0.5)], 0.2, 2);
+    assert_eq!(single.len(), 2 * 3);
+    assert!(single
+        .iter()
+        .filter(|p| !p.is_blank())
+        .all(|p| *p == lit(0.5, 0.5)));
+}
+
+#[test]
+fn normalize_point_count_keeps_the_ends() {
+    let points = [lit(-1., 0.), lit(0., 0.), lit(1., 0.)];
+    let normalized = normalize_point_count(&points, 5);
+
+    assert_eq!(normalized.len(), 5);
+    assert_eq!(normalized[0], points[0]);
+    assert_at(&normalized[1], -0.5, 0.);
+    assert_eq!(normalized[4], points[2]);
+    assert_eq!(normalize_point_count(&points, 2).len(), 2);
+}
+
+#[test]
+fn normalize_point_count_handles_trivial_input() {
+    assert!(normalize_point_count(&[], 10).is_empty());
+    assert_eq!(normalize_point_count(&[lit(0., 0.)], 3), [lit(0., 0.); 3]);
+    assert!(normalize_point_count(&[lit(0., 0.), lit(1., 0.)], 0).is_empty());
+}
+
+#[test]
+fn kaleidoscope_rotates_copies() {
+    let copies = kaleidoscope(&[lit(0.5, 0.)], 4);
+
+    assert_eq!(copies.len(), 4 * 3);
+    let lit_points: Vec<_> = copies.iter().filter(|p| !p.is_blank()).collect();
+    assert_at(lit_points[0], 0.5, 0.);
+    assert_at(lit_points[1], 0., 0.5);
+    assert_at(lit_points[2], -0.5, 0.);
+    assert_at(lit_points[3], 0., -0.5);
+}
+
+#[test]
+fn kaleidoscope_mirrored_flips_every_other_copy() {
+    let plain = kaleidoscope(&[lit(0.5, 0.25)], 2);
+    let mirrored = kaleidoscope_mirrored(&[lit(0.5, 0.25)], 2);
+
+    assert_at(&plain[1], 0.5, 0.25);
+    assert_at(&plain[4], -0.5, -0.25);
+    assert_at(&mirrored[1], 0.5, 0.25);
+    assert_at(&mirrored[4], -0.5, 0.25);
+}
+
+#[test]
+fn kaleidoscope_handles_trivial_input() {
+    assert!(kaleidoscope(&[], 6).is_empty());
+    assert!(kaleidoscope_mirrored(&[], 6).is_empty());
+    assert!(kaleidoscope(&[lit(0.5, 0.)], 0).is_empty());
+    assert_eq!(kaleidoscope(&[lit(0.5, 0.)], 1).len(), 3);
+}
+
+#[test]
+fn dedup_collapses_runs() {
+    let (a, b) = (lit(0., 0.), blank(0.5, 0.5));
+    let points = [a, a, b, b, b, a];
+
+    assert_eq!(dedup_consecutive(&points), [a, b, a]);
+    assert_eq!(dedup_consecutive_keep(&points, 2), [a, a, b, b, a]);
+    assert!(dedup_consecutive(&[]).is_empty());
+    assert_eq!(dedup_consecutive(&[a]), [a]);
+}
+
+#[test]
+fn blank_bracketed_adds_dark_ends() {
+    let bracketed = blank_bracketed(vec![lit(-0.5, 0.), lit(0.5, 0.)]);
+
+    assert_eq!(bracketed.len(), 4);
+    assert_eq!(bracketed[0], blank(-0.5, 0.));
+    assert_eq!(bracketed[3], blank(0.5, 0.));
+    assert!(blank_bracketed(vec![]).is_empty());
+    assert_eq!(
+        blank_bracketed(vec![lit(0.5, 0.5)]),
+        [blank(0.5, 0.5), lit(0.5, 0.5), blank(0.5, 0.5)]
+    );
+}
@@ -0,0 +1,167 @@
+use lasercube::{
+    ilda::{parse, IldaError},
+    LaserdockSample,
+};
+use lasy::{IsBlank, Position};
+
+const LAST_POINT: u8 = 0x80;
+const BLANKED: u8 = 0x40;
+
+fn header(format: u8, records: u16) -> Vec<u8> {
+    let mut header = b"ILDA".to_vec();
+    header.extend([0, 0, 0, format]);
+    header.extend([0; 16]);
+    header.extend(records.to_be_bytes());
+    header.extend([0; 6]);
+    header
+}
+
+fn section(format: u8, records: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = header(format, records.len() as u16);
+    for record in records {
+        bytes.extend(record);
+    }
+    bytes
+}
+
+fn xy(x: i16, y: i16) -> Vec<u8> {
+    let mut bytes = x.to_be_bytes().to_vec();
+    bytes.extend(y.to_be_bytes());
+    bytes
+}
+
+fn record(xy_bytes: Vec<u8>, rest: &[u8]) -> Vec<u8> {
+    let mut bytes = xy_bytes;
+    bytes.extend(rest);
+    bytes
+}
+
+fn end() -> Vec<u8> {
+    header(0, 0)
+}
+
+fn assert_close(sample: &LaserdockSample, x: f32, y: f32) {
+    let [px, py] = sample.position();
+    assert!(
+        (px - x).abs() < 1e-3 && (py - y).abs() < 1e-3,
+        "{:?}",
+        sample
+    );
+}
+
+// two true-color points, the second blanked and marked as the last one.
+fn format_5_file() -> Vec<u8> {
+    let mut file = section(
+        5,
+        &[
+            record(xy(i16::MAX, 0), &[0, 30, 20, 10]),
+            record(xy(0, -i16::MAX), &[LAST_POINT | BLANKED, 30, 20, 10]),
+        ],
+    );
+    file.extend(end());
+    file
+}
+
+#[test]
+fn format_5_is_true_color_2d() {
+    let frames = parse(&format_5_file()).unwrap();
+
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0][0].rgb(), (10, 20, 30));
+    assert_close(&frames[0][0], 1., 0.);
+    assert!(frames[0][1].is_blank());
+    assert_close(&frames[0][1], 0., -1.);
+}
+
+#[test]
+fn format_4_is_true_color_3d() {
+    let mut file = section(4, &[record(xy(0, i16::MAX), &[0, 0, 0, 30, 20, 10])]);
+    file.extend(end());
+    let frames = parse(&file).unwrap();
+
+    assert_eq!(frames[0][0].rgb(), (10, 20, 30));
+    assert_close(&frames[0][0], 0., 1.);
+}
+
+#[test]
+fn last_point_bit_alone_keeps_the_point_lit() {
+    let mut file = section(5, &[record(xy(0, 0), &[LAST_POINT, 0, 0, 255])]);
+    file.extend(end());
+    let frames = parse(&file).unwrap();
+
+    assert_eq!(frames[0][0].rgb(), (255, 0, 0));
+}
+
+#[test]
+fn indexed_formats_use_the_latest_palette() {
+    let mut file = section(2, &[vec![255, 0, 0], vec![0, 0, 255]]);
+    file.extend(section(0, &[record(xy(0, 0), &[0, 0, 0, 1])]));
+    file.extend(section(1, &[record(xy(0, 0), &[BLANKED, 0])]));
+    file.extend(section(1, &[record(xy(0, 0), &[0, 0])]));
+    file.extend(end());
+    let frames = parse(&file).unwrap();
+
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames[0][0].rgb(), (0, 0, 255));
+    assert!(frames[1][0].is_blank());
+    assert_eq!(frames[2][0].rgb(), (255, 0, 0));
+}
+
+#[test]
+fn indexed_formats_without_palette_are_white() {
+    let mut file = section(1, &[record(xy(0, 0), &[0, 7])]);
+    file.extend(end());
+    let frames = parse(&file).unwrap();
+
+    assert_eq!(frames[0][0].rgb(), (255, 255, 255));
+}
+
+#[test]
+fn missing_end_section_is_accepted() {
+    let mut file = format_5_file();
+    file.truncate(file.len() - 32);
+
+    assert_eq!(parse(&file).unwrap().len(), 1);
+}
+
+#[test]
+fn bad_magic_is_an_error() {
+    let mut file = format_5_file();
+    file[0] = b'X';
+
+    assert!(matches!(parse(&file), Err(IldaError::BadMagic(0))));
+}
+
+#[test]
+fn unsupported_format_is_an_error() {
+    let file = section(3, &[vec![0; 4]]);
+
+    assert!(matches!(parse(&file), Err(IldaError::UnsupportedFormat(3))));
+}
+
+#[test]
+fn truncated_data_is_an_error() {
+    let mut file = format_5_file();
+    file.truncate(32 + 12);
+
+    assert!(matches!(parse(&file), Err(IldaError::Truncated(32))));
+    assert!(matches!(parse(&file[..10]), Err(IldaError::Truncated(0))));
+}
+
+#[test]
+fn garbage_never_panics() {
+    let file = format_5_file();
+    for len in 0..file.len() {
+        let _ = parse(&file[..len]);
+    }
+
+    let noise: Vec<u8> = (0..512u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+        .collect();
+    assert!(parse(&noise).is_err());
+    for format in 0..=255 {
+        let mut file = header(format, u16::MAX);
+        file.extend(&noise);
+        let _ = parse(&file);
+    }
+}
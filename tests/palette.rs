@@ -0,0 +1,84 @@
+use lasercube::{
+    palette::{apply_palette_along, to_monochrome, Channel, Palette},
+    LaserdockSample,
+};
+use lasy::{IsBlank, Position};
+
+#[test]
+fn monochrome_puts_luminance_on_one_channel() {
+    let white = LaserdockSample::new(255, 255, 255, 0.5, -0.5);
+    let red = LaserdockSample::new(255, 0, 0, 0., 0.);
+
+    let mono = to_monochrome(&[white, red], Channel::G);
+
+    assert_eq!(mono[0].rgb(), (0, 255, 0));
+    assert_eq!(mono[0].position(), white.position());
+    assert_eq!(mono[1].rgb(), (0, 76, 0));
+    assert_eq!(to_monochrome(&[red], Channel::B)[0].rgb(), (0, 0, 76));
+}
+
+#[test]
+fn monochrome_keeps_dim_points_lit() {
+    let dim: Vec<_> = (1..=4)
+        .map(|b| LaserdockSample::new(0, 0, b, 0., 0.))
+        .collect();
+
+    for point in to_monochrome(&dim, Channel::R) {
+        assert_eq!(point.rgb(), (1, 0, 0));
+    }
+}
+
+#[test]
+fn monochrome_passes_blanked_points_through() {
+    let blank = LaserdockSample::new(0, 0, 0, 0.25, 0.75);
+
+    assert_eq!(to_monochrome(&[blank], Channel::R), [blank]);
+    assert!(to_monochrome(&[], Channel::R).is_empty());
+}
+
+#[test]
+fn palette_interpolates_and_wraps() {
+    let palette = Palette::new(vec![(0, 0, 0), (200, 100, 0)]);
+
+    assert_eq!(palette.at(0.0), (0, 0, 0));
+    assert_eq!(palette.at(0.25), (100, 50, 0));
+    assert_eq!(palette.at(0.5), (200, 100, 0));
+    assert_eq!(palette.at(1.0), (0, 0, 0));
+    assert_eq!(palette.at(-0.5), (200, 100, 0));
+    assert_eq!(Palette::new(vec![]).at(0.3), (0, 0, 0));
+    assert_eq!(
+        Palette::rainbow(3).colors(),
+        [(255, 0, 0), (0, 255, 0), (0, 0, 255)]
+    );
+}
+
+#[test]
+fn palette_is_spread_over_lit_points() {
+    let palette = Palette::new(vec![(255, 0, 0), (0, 0, 255)]);
+    let points = [
+        LaserdockSample::new(9, 9, 9, -0.5, 0.),
+        LaserdockSample::new(0, 0, 0, 0., 0.),
+        LaserdockSample::new(9, 9, 9, 0.5, 0.),
+    ];
+    let colored = apply_palette_along(&points, &palette, 0.);
+
+    assert_eq!(colored[0].rgb(), (255, 0, 0));
+    assert!(colored[1].is_blank());
+    assert_eq!(colored[2].rgb(), (0, 0, 255));
+    assert_eq!(
+        apply_palette_along(&points, &palette, 0.5)[0].rgb(),
+        (0, 0, 255)
+    );
+}
+
+#[test]
+fn palette_handles_trivial_input() {
+    let palette = Palette::rainbow(6);
+    let single = LaserdockSample::new(9, 9, 9, 0., 0.);
+
+    assert!(apply_palette_along(&[], &palette, 0.).is_empty());
+    assert_eq!(
+        apply_palette_along(&[single], &palette, 0.)[0].rgb(),
+        (255, 0, 0)
+    );
+}
@@ -0,0 +1,46 @@
+use lasercube::{interpolation::optimize, LaserdockSample};
+use lasy::{InterpolationConfig, IsBlank, Position};
+
+fn square() -> Vec<LaserdockSample> {
+    let corners = [
+        (-0.5, -0.5),
+        (0.5, -0.5),
+        (0.5, 0.5),
+        (-0.5, 0.5),
+        (-0.5, -0.5),
+    ];
+    let mut points = vec![LaserdockSample::new(0, 0, 0, -0.5, -0.5)];
+    points.extend(
+        corners
+            .iter()
+            .map(|&(x, y)| LaserdockSample::new(255, 0, 0, x, y)),
+    );
+    points
+}
+
+#[test]
+fn optimize_reaches_target_and_keeps_the_drawing() {
+    let input = square();
+    let output = optimize(&input, 200, &InterpolationConfig::default());
+
+    assert!(output.len() >= 200, "got {} points", output.len());
+    assert!(output.iter().any(|p| !p.is_blank()));
+
+    let lit_input = input.iter().filter(|p| !p.is_blank());
+    for corner in lit_input {
+        assert!(
+            output
+                .iter()
+                .any(|p| !p.is_blank() && p.position() == corner.position()),
+            "corner {:?} missing from output",
+            corner
+        );
+    }
+}
+
+#[test]
+fn optimize_drops_frames_without_lit_points() {
+    let input: Vec<_> = square().iter().map(|p| p.with_rgb(0, 0, 0)).collect();
+
+    assert!(optimize(&input, 200, &InterpolationConfig::default()).is_empty());
+}
@@ -0,0 +1,20 @@
+use lasercube::{LaserdockSample, XY, XY_MAX};
+use lasy::Position;
+
+#[test]
+fn position_spans_the_normalized_field() {
+    let corner = |x: u16, y: u16| LaserdockSample::new_xy(255, 0, 0, XY(x), XY(y)).position();
+
+    assert_eq!(corner(0, 0), [-1.0, -1.0]);
+    assert_eq!(corner(XY_MAX, XY_MAX), [1.0, 1.0]);
+    assert_eq!(corner(0, XY_MAX), [-1.0, 1.0]);
+}
+
+#[test]
+fn position_round_trips_normalized_coordinates() {
+    for &v in &[-1.0, -0.5, 0.0, 0.5, 1.0] {
+        let [x, y] = LaserdockSample::new(255, 0, 0, v, -v).position();
+        assert!((x as f64 - v).abs() < 1e-3, "{}", v);
+        assert!((y as f64 + v).abs() < 1e-3, "{}", v);
+    }
+}
@@ -0,0 +1,53 @@
+#![cfg(feature = "serde")]
+
+use lasercube::{profile::CalibrationProfile, RenderConfig, StationaryWatchdog};
+
+#[test]
+fn profile_round_trips_through_a_file() {
+    let profile = CalibrationProfile {
+        render: RenderConfig {
+            flip: (true, false),
+            brightness: 0.75,
+            white_balance: [1.0, 0.875, 0.75],
+            max_combined_power: 600,
+            scan_scale: (0.875, 0.5),
+            stationary_watchdog: Some(StationaryWatchdog::default()),
+            preroll_points: 8,
+            ..RenderConfig::default()
+        },
+        dac_rate: Some(30_000),
+    };
+    let path = std::env::temp_dir().join(format!("lasercube-profile-{}.toml", std::process::id()));
+
+    profile.save(&path).unwrap();
+    let loaded = CalibrationProfile::load(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.unwrap(), profile);
+}
+
+#[test]
+fn missing_settings_keep_their_defaults() {
+    let path = std::env::temp_dir().join(format!("lasercube-partial-{}.toml", std::process::id()));
+    std::fs::write(&path, "flip = [true, false]\ndac_rate = 20000\n").unwrap();
+    let loaded = CalibrationProfile::load(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    let mut expected = CalibrationProfile {
+        dac_rate: Some(20_000),
+        ..CalibrationProfile::default()
+    };
+    expected.render.flip = (true, false);
+    assert_eq!(loaded.unwrap(), expected);
+}
+
+#[test]
+fn default_profile_round_trips() {
+    let path = std::env::temp_dir().join(format!("lasercube-default-{}.toml", std::process::id()));
+
+    CalibrationProfile::default().save(&path).unwrap();
+    let loaded = CalibrationProfile::load(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.unwrap(), CalibrationProfile::default());
+}
@@ -0,0 +1,53 @@
+use std::{thread, time::Duration};
+
+use lasercube::{
+    animation::Frame,
+    queue::{FrameQueue, QueuePolicy},
+    LaserdockSample,
+};
+
+fn frame() -> Frame {
+    Frame::new(vec![LaserdockSample::new(255, 0, 0, 0., 0.)])
+}
+
+#[test]
+fn blocking_push_waits_for_room() {
+    let queue = FrameQueue::new(1, QueuePolicy::Block);
+    assert!(!queue.push(frame()));
+
+    let consumer = queue.clone();
+    let popper = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        consumer.try_pop().is_some()
+    });
+
+    assert!(!queue.push(frame()));
+    assert!(popper.join().unwrap());
+    assert_eq!(queue.len(), 1);
+}
+
+#[test]
+fn blocking_push_to_a_closed_full_queue_drops_the_frame() {
+    let queue = FrameQueue::new(1, QueuePolicy::Block);
+    queue.push(frame());
+    queue.close();
+
+    assert!(queue.push(frame()));
+    assert_eq!(queue.len(), 1);
+}
+
+#[test]
+fn close_wakes_a_blocked_push() {
+    let queue = FrameQueue::new(1, QueuePolicy::Block);
+    queue.push(frame());
+
+    let closer = queue.clone();
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        closer.close();
+    });
+
+    assert!(queue.push(frame()));
+    handle.join().unwrap();
+    assert_eq!(queue.len(), 1);
+}
@@ -0,0 +1,54 @@
+use bytemuck::cast_slice;
+use lasercube::{LaserdockSample, SAMPLE_SIZE, XY};
+
+#[test]
+fn wire_layout_is_pinned() {
+    let sample = LaserdockSample::new_xy(0x12, 0x34, 0x56, XY(0x789), XY(0xabc));
+
+    assert_eq!(
+        sample.to_wire_bytes(),
+        [0x12, 0x34, 0x56, 0x00, 0x89, 0x07, 0xbc, 0x0a]
+    );
+}
+
+#[test]
+fn wire_bytes_match_memory_layout_on_little_endian() {
+    let samples = [
+        LaserdockSample::new_xy(1, 2, 3, XY(4), XY(5)),
+        LaserdockSample::new_xy(255, 0, 128, XY(4095), XY(0)),
+    ];
+    let serialized: Vec<u8> = samples.iter().flat_map(|s| s.to_wire_bytes()).collect();
+
+    assert_eq!(serialized.len(), samples.len() * SAMPLE_SIZE);
+    if cfg!(target_endian = "little") {
+        assert_eq!(serialized, cast_slice::<_, u8>(&samples));
+    }
+}
+
+#[test]
+fn xy_conversion_rounds_and_clamps() {
+    let cases: [(f64, u16); 7] = [
+        (-1.5, 0),
+        (-1.0, 0),
+        (-0.5, 1024),
+        (0.0, 2048),
+        (0.5, 3071),
+        (1.0, 4095),
+        (1.5, 4095),
+    ];
+
+    for (input, expected) in cases {
+        assert_eq!(XY::from(input as f32), XY(expected), "f32 {}", input);
+        assert_eq!(XY::from(input), XY(expected), "f64 {}", input);
+    }
+}
+
+#[test]
+fn wire_bytes_decode_back() {
+    let sample = LaserdockSample::new_xy(0x12, 0x34, 0x56, XY(0x789), XY(0xabc));
+
+    assert_eq!(
+        LaserdockSample::from_wire_bytes(sample.to_wire_bytes()),
+        sample
+    );
+}